@@ -0,0 +1,128 @@
+//! Compares the streaming tokenizer used for meta tags against the regex scanning it replaced,
+//! and against building a full DOM, as is still done for sites with field selectors.
+//!
+//! Run with `cargo bench -p embed-server --bench html`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use regex::Regex;
+
+#[allow(dead_code)]
+#[path = "../src/parser/tokenizer.rs"]
+mod tokenizer;
+
+/// Builds a page roughly shaped like a typical article, with a large body after the head
+fn fixture(paragraphs: usize) -> String {
+    let mut html = String::from(
+        r#"<!DOCTYPE html><html lang="en"><head>
+        <meta charset="utf-8">
+        <title>Some Article Title &amp; More</title>
+        <meta name="viewport" content="width=device-width, initial-scale=1">
+        <meta property="og:title" content="Some Article Title">
+        <meta property="og:description" content="A description of the article, which is fairly long.">
+        <meta property="og:image" content="https://example.com/images/article.jpg">
+        <meta property="og:image:width" content="1200">
+        <meta property="og:image:height" content="630">
+        <meta name="twitter:card" content="summary_large_image">
+        <link rel="canonical" href="https://example.com/article">
+        <link rel="icon" sizes="32x32" href="/favicon-32.png">
+        <script type="application/ld+json">{"@type":"NewsArticle","headline":"Some Article Title"}</script>
+        <script>window.dataLayer = window.dataLayer || []; if (a < b) { track("<div>"); }</script>
+        <style>.article > p { margin: 0 }</style>
+        </head><body><div class="article">"#,
+    );
+
+    for i in 0..paragraphs {
+        html += &format!(
+            r#"<div class="paragraph" data-index="{i}"><p>Lorem ipsum dolor sit amet, <a href="/link/{i}">consectetur</a>
+            adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.</p>
+            <span class="caption">Caption {i}</span></div>"#
+        );
+    }
+
+    html += "</div></body></html>";
+    html
+}
+
+/// The regexes previously used to find meta tags
+fn regex_scan(html: &str, tags: &Regex, attrs: &Regex) -> usize {
+    let mut count = 0;
+
+    for m in tags.find_iter(html) {
+        let start = m.end();
+
+        if let Some(end) = memchr::memchr(b'>', html[start..].as_bytes()) {
+            count += attrs.find_iter(&html[start..(start + end)]).count();
+        }
+    }
+
+    count
+}
+
+fn tokenize(html: &str, head_only: bool) -> usize {
+    let mut count = 0;
+
+    for token in tokenizer::Tokenizer::new(html) {
+        match token {
+            tokenizer::Token::StartTag(tag) if head_only && tag.name == "body" => break,
+            tokenizer::Token::StartTag(tag) => count += tag.attrs().count(),
+            _ => {}
+        }
+    }
+
+    count
+}
+
+fn bench_html(c: &mut Criterion) {
+    let tags = Regex::new(
+        r"(?x)
+        <(?i)(
+        meta\x20|
+        title[^>]*>|
+        link\x20|
+        script[^>]+ld\+json[^>]*>|
+        /?(div|span|article|section)[\x20\t\r\n>]
+    )",
+    )
+    .unwrap();
+
+    let attrs = Regex::new(
+        r#"(?x)
+        [a-zA-Z_][0-9a-zA-Z\-_]+\s*=\s*(
+        ("(?:\\"|[^"])*[^\\]")|
+        ('(?:\\'|[^'])*[^\\]')|
+        ([^'"](?:\\\s|[^\s>]*))
+    )"#,
+    )
+    .unwrap();
+
+    let mut group = c.benchmark_group("html");
+
+    for paragraphs in [10, 100, 1000] {
+        let html = fixture(paragraphs);
+
+        group.throughput(Throughput::Bytes(html.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("regex", paragraphs), &html, |b, html| {
+            b.iter(|| regex_scan(html, &tags, &attrs))
+        });
+
+        group.bench_with_input(BenchmarkId::new("tokenizer", paragraphs), &html, |b, html| {
+            b.iter(|| tokenize(html, false))
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("tokenizer_head", paragraphs),
+            &html,
+            |b, html| b.iter(|| tokenize(html, true)),
+        );
+
+        group.bench_with_input(BenchmarkId::new("dom", paragraphs), &html, |b, html| {
+            b.iter(|| scraper::Html::parse_document(html))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_html);
+criterion_main!(benches);
@@ -0,0 +1,175 @@
+//! Cross-instance cache invalidation through Redis pub/sub
+//!
+//! Every replica subscribes to the same channel, and whenever an embed is refreshed or purged
+//! on one replica, the others evict that key from their in-memory cache (and optionally their local tiers).
+
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use fred::{
+    clients::{Client, SubscriberClient},
+    interfaces::{ClientLike as _, EventInterface as _, PubsubInterface as _},
+    types::config::Config,
+};
+use hashbrown::HashMap;
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{config::ConfigError, error::Error, state::ServiceState};
+
+pub struct InvalidationBus {
+    publisher: Client,
+    subscriber: SubscriberClient,
+    channel: String,
+
+    /// Also evict invalidated keys from storage tiers that aren't shared between replicas
+    local_tiers: bool,
+
+    /// Random identifier to ignore messages published by this instance
+    instance: [u8; 8],
+
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl InvalidationBus {
+    /// Create the bus from the `[cache.redis]` config, if `invalidation_channel` is set
+    pub fn create(config: &HashMap<String, String>) -> Result<Option<Self>, Error> {
+        let Some(channel) = config.get("invalidation_channel") else {
+            return Ok(None);
+        };
+
+        let Some(url) = config.get("url") else {
+            return Err(Error::ConfigError(ConfigError::MissingCacheField("redis.url")));
+        };
+
+        let mut local_tiers = false;
+
+        if let Some(local) = config.get("invalidate_local_tiers") {
+            local_tiers = local.parse().map_err(|_| {
+                Error::ConfigError(ConfigError::InvalidCacheField("redis.invalidate_local_tiers"))
+            })?;
+        }
+
+        let config = Config::from_url(url)?;
+
+        let publisher = Client::new(config.clone(), None, None, None);
+        let subscriber = SubscriberClient::new(config, None, None, None);
+
+        publisher.connect();
+        subscriber.connect();
+
+        Ok(Some(InvalidationBus {
+            publisher,
+            subscriber,
+            channel: channel.clone(),
+            local_tiers,
+            instance: ahash::RandomState::new().hash_one(std::process::id()).to_le_bytes(),
+            task: Mutex::new(None),
+        }))
+    }
+
+    /// Notify other replicas that the given key is no longer valid
+    pub async fn publish(&self, key: &Bytes) {
+        let mut payload = Vec::with_capacity(self.instance.len() + key.len());
+
+        payload.extend_from_slice(&self.instance);
+        payload.extend_from_slice(key);
+
+        if let Err(e) = self.publisher.publish::<(), _, _>(self.channel.as_str(), Bytes::from(payload)).await
+        {
+            log::error!("Error publishing cache invalidation: {e:?}");
+        }
+    }
+
+    /// Returns the invalidated key, unless the message came from this instance
+    fn parse<'a>(&self, payload: &'a [u8]) -> Option<&'a [u8]> {
+        match payload.split_at_checked(self.instance.len()) {
+            Some((instance, key)) if instance != self.instance => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Unsubscribe and wait for the listener task to finish
+    pub async fn stop(&self) {
+        if let Err(e) = self.subscriber.quit().await {
+            log::error!("Error closing cache invalidation subscriber: {e:?}");
+        }
+
+        let task = self.task.lock().unwrap().take();
+
+        if let Some(task) = task {
+            task.abort();
+            _ = task.await;
+        }
+
+        _ = self.publisher.quit().await;
+    }
+}
+
+/// Spawn the listener task for the cache invalidation bus, if configured.
+///
+/// The task only holds a weak reference to the state, and is stopped by [`EmbedCache::stop_listening`].
+///
+/// [`EmbedCache::stop_listening`]: super::EmbedCache::stop_listening
+pub fn listen(state: &Arc<ServiceState>) {
+    let Some(ref bus) = state.cache.bus else {
+        return;
+    };
+
+    let mut rx = bus.subscriber.message_rx();
+    let weak = Arc::downgrade(state);
+
+    let task = tokio::spawn(async move {
+        {
+            let Some(state) = weak.upgrade() else {
+                return;
+            };
+
+            let Some(ref bus) = state.cache.bus else {
+                return;
+            };
+
+            if let Err(e) = bus.subscriber.wait_for_connect().await {
+                log::error!("Unable to connect cache invalidation subscriber: {e:?}");
+                return;
+            }
+
+            if let Err(e) = bus.subscriber.subscribe(bus.channel.as_str()).await {
+                log::error!("Unable to subscribe to cache invalidation channel: {e:?}");
+                return;
+            }
+
+            // resubscribe after reconnecting
+            _ = bus.subscriber.manage_subscriptions();
+
+            log::info!(channel = %bus.channel, "Listening for cache invalidations");
+        }
+
+        loop {
+            let message = match rx.recv().await {
+                Ok(message) => message,
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!(skipped, "Cache invalidation listener lagged behind");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let Some(state) = weak.upgrade() else {
+                break;
+            };
+
+            let Some(ref bus) = state.cache.bus else {
+                break;
+            };
+
+            let Some(key) = message.value.as_bytes().and_then(|payload| bus.parse(payload)) else {
+                continue;
+            };
+
+            state.cache.apply_invalidation(&Bytes::copy_from_slice(key), bus.local_tiers).await;
+        }
+    });
+
+    *bus.task.lock().unwrap() = Some(task);
+}
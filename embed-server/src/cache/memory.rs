@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use embed::timestamp::Timestamp;
+use embed::v1::{BasicEmbedMedia, EmbedField, EmbedMedia, EmbedV1};
+
+use super::CacheState;
+
+pub type Entry<'a> = scc::hash_map::Entry<'a, Bytes, MemoryEntry, ahash::RandomState>;
+pub type OccupiedEntry<'a> = scc::hash_map::OccupiedEntry<'a, Bytes, MemoryEntry, ahash::RandomState>;
+pub type VacantEntry<'a> = scc::hash_map::VacantEntry<'a, Bytes, MemoryEntry, ahash::RandomState>;
+
+/// Saturation point for the access frequency of each entry,
+/// which is how many eviction passes a frequently-used entry can survive.
+const MAX_FREQUENCY: u8 = 3;
+
+/// Smallest budget that can be set at runtime, as anything less would evict nearly everything
+pub const MIN_BUDGET: usize = 1024 * 1024;
+
+/// In-memory cache tier bounded by an estimate of the memory used by each entry,
+/// rather than the number of entries.
+///
+/// Eviction uses a CLOCK-style FIFO queue with a small saturating frequency counter per entry,
+/// so frequently accessed embeds get a second chance while large or cold embeds are evicted first.
+pub struct MemoryCache {
+    map: scc::HashMap<Bytes, MemoryEntry, ahash::RandomState>,
+
+    /// Insertion queue of `(key, id)` pairs, items whose id no longer
+    /// matches the live entry are stale and dropped when encountered.
+    queue: Mutex<VecDeque<(Bytes, u64)>>,
+
+    next_id: AtomicU64,
+    used: AtomicUsize,
+    budget: AtomicUsize,
+}
+
+pub struct MemoryEntry {
+    pub state: CacheState,
+    id: u64,
+    weight: usize,
+    frequency: AtomicU8,
+}
+
+impl MemoryEntry {
+    /// Mark the entry as recently used
+    pub fn touch(&self) {
+        let _ = self.frequency.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |f| {
+            (f < MAX_FREQUENCY).then_some(f + 1)
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MemoryStats {
+    /// Number of entries currently held in memory
+    pub entries: usize,
+    /// Estimated number of bytes used by those entries
+    pub used: usize,
+    /// Maximum number of bytes to use before evicting entries
+    pub budget: usize,
+}
+
+/// Estimate the number of bytes used by a cache entry
+fn weigh(key: &Bytes, state: &CacheState) -> usize {
+    const OVERHEAD: usize = size_of::<MemoryEntry>() + size_of::<(Bytes, u64)>() * 2;
+
+    let value = match state {
+        #[allow(unreachable_patterns)]
+        CacheState::Ready(e) => {
+            size_of::<embed::EmbedWithExpire>()
+                + match e.1 {
+                    embed::Embed::V1(ref embed) => weigh_embed(embed),
+                    _ => 0,
+                }
+        }
+        CacheState::Errored(_) => size_of::<crate::error::CacheError>(),
+    };
+
+    OVERHEAD + key.len() + value
+}
+
+/// Heap usage of the embed, counting only strings and media. Short inline strings
+/// are counted as well, which is close enough since it's only an estimate.
+fn weigh_embed(embed: &EmbedV1) -> usize {
+    let mut weight =
+        text(&embed.title) + text(&embed.description) + text(&embed.url) + text(&embed.canonical);
+
+    weight += embed.imgs.iter().map(weigh_media).sum::<usize>();
+
+    for media in [&embed.thumb, &embed.video, &embed.audio, &embed.obj] {
+        weight += boxed_media(media);
+    }
+
+    for field in &embed.fields {
+        weight += size_of::<EmbedField>() + field.name.len() + field.value.len() + boxed_media(&field.img);
+    }
+
+    if let Some(ref footer) = embed.footer {
+        weight += footer.text.len() + boxed_media(&footer.icon);
+    }
+
+    if let Some(ref author) = embed.author {
+        weight += author.name.len() + text(&author.url) + boxed_media(&author.icon);
+    }
+
+    weight + text(&embed.provider.name) + text(&embed.provider.url) + boxed_media(&embed.provider.icon)
+}
+
+fn weigh_media(media: &EmbedMedia) -> usize {
+    let alts = media
+        .alts
+        .iter()
+        .map(|alt| size_of::<BasicEmbedMedia>() + alt.url.len() + text(&alt.description) + text(&alt.mime));
+
+    size_of::<EmbedMedia>()
+        + media.url.len()
+        + text(&media.description)
+        + text(&media.mime)
+        + alts.sum::<usize>()
+}
+
+fn boxed_media(media: &Option<Box<EmbedMedia>>) -> usize {
+    media.as_deref().map_or(0, weigh_media)
+}
+
+fn text<S: Deref<Target = str>>(s: &Option<S>) -> usize {
+    s.as_deref().map_or(0, str::len)
+}
+
+impl MemoryCache {
+    pub fn new(budget: usize) -> Self {
+        MemoryCache {
+            map: scc::HashMap::with_hasher(ahash::RandomState::new()),
+            queue: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+            used: AtomicUsize::new(0),
+            budget: AtomicUsize::new(budget),
+        }
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            entries: self.map.len(),
+            used: self.used.load(Ordering::Relaxed),
+            budget: self.budget.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Change the memory budget, evicting entries if the new budget is smaller than current usage
+    pub async fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+        self.evict().await;
+    }
+
+    /// Lock the entry for the given key.
+    ///
+    /// NOTE: [`MemoryCache::evict`] must not be called while an entry is held.
+    pub fn entry(&self, key: Bytes) -> Entry<'_> {
+        self.map.entry(key)
+    }
+
+    /// See [`MemoryCache::entry`]
+    pub async fn entry_async(&self, key: Bytes) -> Entry<'_> {
+        self.map.entry_async(key).await
+    }
+
+    /// Remove the locked entry, returning its state
+    pub fn remove(&self, occ: OccupiedEntry<'_>) -> CacheState {
+        let (_, entry) = occ.remove_entry();
+
+        self.used.fetch_sub(entry.weight, Ordering::Relaxed);
+
+        entry.state
+    }
+
+    /// Remove the entry for the given key, if present
+    pub async fn remove_async(&self, key: &Bytes) -> Option<CacheState> {
+        let (_, entry) = self.map.remove_async(key).await?;
+
+        self.used.fetch_sub(entry.weight, Ordering::Relaxed);
+
+        Some(entry.state)
+    }
+
+    /// Replace the state of the locked entry, keeping its position in the eviction queue
+    pub fn replace(&self, occ: &mut OccupiedEntry<'_>, state: CacheState) {
+        let weight = weigh(occ.key(), &state);
+        let entry = occ.get_mut();
+
+        self.used.fetch_add(weight, Ordering::Relaxed);
+        self.used.fetch_sub(entry.weight, Ordering::Relaxed);
+
+        entry.state = state;
+        entry.weight = weight;
+    }
+
+    /// Insert a new entry into the locked vacant slot
+    ///
+    /// Entries larger than the entire budget are not admitted at all.
+    pub fn insert(&self, vac: VacantEntry<'_>, state: CacheState) {
+        let weight = weigh(vac.key(), &state);
+
+        if weight > self.budget.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.queue.lock().unwrap().push_back((vac.key().clone(), id));
+        self.used.fetch_add(weight, Ordering::Relaxed);
+
+        vac.insert_entry(MemoryEntry {
+            state,
+            id,
+            weight,
+            frequency: AtomicU8::new(0),
+        });
+    }
+
+    /// Insert or replace an entry without holding the lock beforehand
+    pub async fn put_async(&self, key: Bytes, state: CacheState) {
+        match self.map.entry_async(key).await {
+            Entry::Occupied(mut occ) => self.replace(&mut occ, state),
+            Entry::Vacant(vac) => self.insert(vac, state),
+        }
+    }
+
+    /// Evict entries until the estimated memory usage is within budget.
+    ///
+    /// Expired entries are always evicted when encountered, otherwise entries with
+    /// a nonzero frequency have it decremented and are moved to the back of the queue.
+    pub async fn evict(&self) {
+        self.compact_queue();
+
+        let now = Timestamp::now_utc();
+
+        // every entry can be requeued up to MAX_FREQUENCY times, so this bounds the loop
+        let mut remaining = (self.map.len() + 1) * (MAX_FREQUENCY as usize + 1);
+
+        while remaining > 0 && self.used.load(Ordering::Relaxed) > self.budget.load(Ordering::Relaxed) {
+            remaining -= 1;
+
+            let Some((key, id)) = self.queue.lock().unwrap().pop_front() else {
+                break;
+            };
+
+            let mut requeue = false;
+
+            let removed = self
+                .map
+                .remove_if_async(&key, |entry| {
+                    // stale queue item, the entry was removed and inserted again since
+                    if entry.id != id {
+                        return false;
+                    }
+
+                    if entry.state.expires() < now {
+                        return true;
+                    }
+
+                    let frequency = entry.frequency.get_mut();
+
+                    if *frequency > 0 {
+                        *frequency -= 1;
+                        requeue = true;
+
+                        return false;
+                    }
+
+                    true
+                })
+                .await;
+
+            match removed {
+                Some((_, entry)) => {
+                    self.used.fetch_sub(entry.weight, Ordering::Relaxed);
+                }
+                None if requeue => self.queue.lock().unwrap().push_back((key, id)),
+                None => {}
+            }
+        }
+    }
+
+    /// Removed entries leave stale items in the queue, which are only dropped once they reach the front.
+    /// If the queue grows too large compared to the live entries, remove the stale items.
+    fn compact_queue(&self) {
+        let len = self.map.len();
+
+        if self.queue.lock().unwrap().len() <= len * 2 + 64 {
+            return;
+        }
+
+        // take the queue to avoid holding the lock while reading the map,
+        // since inserting into the queue happens while holding an entry lock
+        let mut old = std::mem::take(&mut *self.queue.lock().unwrap());
+
+        old.retain(|(key, id)| self.map.read(key, |_, entry| entry.id == *id).unwrap_or(false));
+
+        let mut queue = self.queue.lock().unwrap();
+
+        // anything added in the meantime is newer, so goes after the old items
+        old.extend(queue.drain(..));
+
+        *queue = old;
+    }
+}
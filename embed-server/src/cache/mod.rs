@@ -1,14 +1,24 @@
 use bytes::Bytes;
-use embed::{timestamp::Timestamp, EmbedWithExpire};
+use embed::{
+    timestamp::{Duration, Timestamp},
+    EmbedWithExpire,
+};
 use futures_util::StreamExt;
-use scc::hash_cache::Entry as CacheEntry;
 use tokio::sync::watch::{self, Receiver, Sender};
 use triomphe::Arc;
 
 use crate::error::{CacheError, Error};
 
+#[cfg(feature = "cache_redis")]
+pub mod invalidation;
+pub mod memory;
+#[cfg(feature = "cache_redis")]
+pub mod single_flight;
 pub mod storage;
-use self::storage::{Cache, CacheStorage, CachedEmbed};
+pub mod tier;
+use self::memory::{Entry as CacheEntry, MemoryCache, MemoryStats};
+use self::storage::{CachedEmbed, ExtractorVersion};
+use self::tier::Tier;
 
 #[derive(Clone)]
 pub enum CacheState {
@@ -29,10 +39,29 @@ impl CacheState {
     }
 }
 
+/// Cap the expiration of the embed to at most `max_ttl` from now
+pub fn cap_ttl(
+    embed: &Arc<EmbedWithExpire>,
+    now: Timestamp,
+    max_ttl: Option<Duration>,
+) -> Arc<EmbedWithExpire> {
+    match max_ttl {
+        Some(max_ttl) if now + max_ttl < embed.0 => Arc::new((now + max_ttl, embed.1.clone())),
+        _ => embed.clone(),
+    }
+}
+
 pub struct EmbedCache {
-    cache: scc::HashCache<Bytes, CacheState, ahash::RandomState>,
+    cache: MemoryCache,
+    memory_max_ttl: Option<Duration>,
     pending: scc::HashIndex<Bytes, Sender<Option<CacheState>>, ahash::RandomState>,
-    storage: Vec<Cache>,
+    storage: Vec<Tier>,
+
+    #[cfg(feature = "cache_redis")]
+    bus: Option<invalidation::InvalidationBus>,
+
+    #[cfg(feature = "cache_redis")]
+    lock: Option<single_flight::DistributedLock>,
 }
 
 pub struct CacheMiss {
@@ -49,13 +78,125 @@ pub enum CacheHit {
     Miss(CacheMiss),
 }
 
+/// Held by the instance fetching an embed after a miss, see [`EmbedCache::single_flight`]
+#[must_use]
+#[derive(Default)]
+pub struct Lease {
+    /// Lock key and token, if a distributed lease was acquired
+    #[cfg_attr(not(feature = "cache_redis"), allow(dead_code))]
+    held: Option<(Bytes, String)>,
+}
+
+pub enum SingleFlight {
+    /// This instance is responsible for fetching the embed, and must release the lease afterwards
+    Fetch(Lease),
+    /// Another instance fetched the embed while waiting
+    Fetched(Arc<EmbedWithExpire>),
+}
+
 impl EmbedCache {
-    pub fn new(capacity: usize) -> Self {
+    /// Create a new cache with the given in-memory budget, in bytes,
+    /// and optionally the maximum time to keep entries in memory.
+    pub fn new(memory_budget: usize, memory_max_ttl: Option<Duration>) -> Self {
         EmbedCache {
-            cache: scc::HashCache::with_capacity_and_hasher(0, capacity, ahash::RandomState::new()),
+            cache: MemoryCache::new(memory_budget),
+            memory_max_ttl,
             pending: scc::HashIndex::default(),
             storage: Vec::new(),
+
+            #[cfg(feature = "cache_redis")]
+            bus: None,
+
+            #[cfg(feature = "cache_redis")]
+            lock: None,
+        }
+    }
+
+    #[cfg(feature = "cache_redis")]
+    pub fn set_invalidation_bus(&mut self, bus: invalidation::InvalidationBus) {
+        self.bus = Some(bus);
+    }
+
+    #[cfg(feature = "cache_redis")]
+    pub fn set_distributed_lock(&mut self, lock: single_flight::DistributedLock) {
+        self.lock = Some(lock);
+    }
+
+    /// After a miss, coordinate with other instances so only one of them fetches the embed.
+    ///
+    /// Without a distributed lock configured, this always returns [`SingleFlight::Fetch`] immediately.
+    pub async fn single_flight(&self, _key: &Bytes, _version: &ExtractorVersion) -> SingleFlight {
+        #[cfg(feature = "cache_redis")]
+        if let Some(ref lock) = self.lock {
+            return self.single_flight_distributed(lock, _key, _version).await;
+        }
+
+        SingleFlight::Fetch(Lease::default())
+    }
+
+    /// Release the lease after the fetched embed has been stored with [`EmbedCache::put`]
+    pub async fn release(&self, lease: Lease) {
+        #[cfg(feature = "cache_redis")]
+        if let Some(ref lock) = self.lock {
+            return lock.release(lease).await;
+        }
+
+        drop(lease);
+    }
+
+    /// Stop listening for invalidations from other instances, must be done before shutdown
+    pub async fn stop_listening(&self) {
+        #[cfg(feature = "cache_redis")]
+        if let Some(ref bus) = self.bus {
+            bus.stop().await;
+        }
+    }
+
+    /// Notify other instances that the embed for this key has changed, if configured
+    async fn publish_invalidation(&self, _key: &Bytes) {
+        #[cfg(feature = "cache_redis")]
+        if let Some(ref bus) = self.bus {
+            bus.publish(_key).await;
+        }
+    }
+
+    /// Purge an embed from every cache tier, on this and every other instance
+    pub async fn invalidate(&self, key: Bytes) {
+        self.cache.remove_async(&key).await;
+
+        futures_util::stream::iter(&self.storage)
+            .for_each_concurrent(None, |storage| async {
+                if let Err(e) = storage.del(key.clone()).await {
+                    tracing::error!("Error purging from cache storage: {e:?}");
+                }
+            })
+            .await;
+
+        self.publish_invalidation(&key).await;
+    }
+
+    /// Handle an invalidation from another instance. Storage shared between instances
+    /// has already been updated by the other instance, so only local tiers are considered.
+    pub async fn apply_invalidation(&self, key: &Bytes, local_tiers: bool) {
+        self.cache.remove_async(key).await;
+
+        // wake up anything waiting on another instance to fetch this key
+        #[cfg(feature = "cache_redis")]
+        if let Some(ref lock) = self.lock {
+            lock.notify(key).await;
         }
+
+        if !local_tiers {
+            return;
+        }
+
+        futures_util::stream::iter(self.storage.iter().filter(|storage| !storage.is_shared()))
+            .for_each_concurrent(None, |storage| async {
+                if let Err(e) = storage.del(key.clone()).await {
+                    tracing::error!("Error invalidating cache storage: {e:?}");
+                }
+            })
+            .await;
     }
 
     pub async fn shutdown(self) {
@@ -68,37 +209,105 @@ impl EmbedCache {
             .await;
     }
 
-    pub fn add_storage(&mut self, storage: Cache) {
+    pub fn add_storage(&mut self, storage: Tier) {
         self.storage.push(storage);
     }
 
-    async fn get_tiered(&self, key: Bytes, now: Timestamp) -> Result<Option<CachedEmbed>, Error> {
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.cache.stats()
+    }
+
+    /// Change the in-memory budget at runtime, in bytes
+    pub async fn set_memory_budget(&self, budget: usize) {
+        self.cache.set_budget(budget).await;
+    }
+
+    /// The state as held in memory, with the expiration capped to `memory_max_ttl`
+    fn for_memory(&self, state: &CacheState, now: Timestamp) -> CacheState {
+        match state {
+            CacheState::Ready(e) => CacheState::Ready(cap_ttl(e, now, self.memory_max_ttl)),
+            CacheState::Errored(_) => state.clone(),
+        }
+    }
+
+    /// Errors from individual tiers are logged and treated as misses, so a
+    /// failing tier only costs performance rather than failing the request.
+    async fn get_tiered(
+        &self,
+        key: Bytes,
+        now: Timestamp,
+        version: &ExtractorVersion,
+    ) -> Option<CachedEmbed> {
         // explore cache storages in order
         for i in 0..self.storage.len() {
-            if let Some(embed) = self.storage[i].get(now, key.clone()).await? {
-                // backpropagate to previous storages in reverse order
-                // so that the highest priority storage is the most recently updated
-                for j in (0..i).rev() {
-                    self.storage[j].put(now, key.clone(), embed.clone()).await?;
+            let embed = match self.storage[i].get(now, key.clone()).await {
+                Ok(Some(embed)) => embed,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!(
+                        "Error reading from cache tier {}: {e:?}",
+                        self.storage[i].name.name()
+                    );
+                    continue;
                 }
+            };
 
-                return Ok(Some(embed));
+            // produced by an outdated extractor, treat as a miss and let it be overwritten
+            if embed.version.is_stale(version) {
+                continue;
             }
+
+            // backpropagate to previous storages in reverse order
+            // so that the highest priority storage is the most recently updated
+            for j in (0..i).rev() {
+                if !self.storage[j].policy().backfill {
+                    continue;
+                }
+
+                if let Err(e) = self.storage[j].put(now, key.clone(), embed.clone()).await {
+                    tracing::warn!(
+                        "Error backfilling cache tier {}: {e:?}",
+                        self.storage[j].name.name()
+                    );
+                }
+            }
+
+            return Some(embed);
         }
 
-        Ok(None)
+        None
+    }
+
+    /// Update the cache with a freshly extracted embed, produced by the given extractor version
+    pub async fn put(&self, key: Bytes, miss: CacheMiss, embed: CacheState, version: ExtractorVersion) {
+        self.put_inner(key, miss, embed, Some(version)).await
     }
 
-    pub async fn put(&self, key: Bytes, miss: CacheMiss, mut embed: CacheState) {
-        let mut propogate = true;
+    /// Complete a miss with an embed that another instance already stored, see [`SingleFlight::Fetched`]
+    pub async fn fill(&self, key: Bytes, miss: CacheMiss, embed: Arc<EmbedWithExpire>) {
+        self.put_inner(key, miss, CacheState::Ready(embed), None).await
+    }
+
+    /// If `version` is `None`, the embed came from storage and is not propagated again
+    async fn put_inner(
+        &self,
+        key: Bytes,
+        miss: CacheMiss,
+        mut embed: CacheState,
+        version: Option<ExtractorVersion>,
+    ) {
+        let mut propogate = version.is_some();
+
+        let now = Timestamp::now_utc();
+        let stored = self.for_memory(&embed, now);
 
         match self.cache.entry_async(key.clone()).await {
             CacheEntry::Occupied(mut occ) => {
-                let old = occ.get();
+                let old = &occ.get().state;
 
                 // if the entry has an earlier expiration or errored, replace it
-                if old.expires() < embed.expires() || old.is_err() {
-                    occ.put(embed.clone());
+                if old.expires() < stored.expires() || old.is_err() {
+                    self.cache.replace(&mut occ, stored);
                 } else {
                     // otherwise go with the latest
                     embed = old.clone();
@@ -106,18 +315,26 @@ impl EmbedCache {
                 }
             }
             CacheEntry::Vacant(vac) => {
-                vac.put_entry(embed.clone());
+                self.cache.insert(vac, stored);
             }
         }
 
-        if propogate {
-            let now = Timestamp::now_utc();
+        // entry lock has been released, so it's safe to evict now
+        self.cache.evict().await;
 
+        if let (true, Some(version)) = (propogate, version) {
             futures_util::stream::iter(&self.storage)
                 .for_each_concurrent(None, |storage| async {
                     let res = match embed.clone() {
                         CacheState::Errored(_) => storage.del(key.clone()).await,
-                        CacheState::Ready(e) => storage.put(now, key.clone(), e).await,
+                        CacheState::Ready(e) => {
+                            let value = CachedEmbed {
+                                embed: e,
+                                version: version.clone(),
+                            };
+
+                            storage.put(now, key.clone(), value).await
+                        }
                     };
 
                     if let Err(e) = res {
@@ -125,6 +342,8 @@ impl EmbedCache {
                     }
                 })
                 .await;
+
+            self.publish_invalidation(&key).await;
         }
 
         miss.tx.send_replace(Some(embed));
@@ -134,7 +353,8 @@ impl EmbedCache {
         drop(miss); // always do this last for any pending get requests
     }
 
-    pub async fn get(&self, key: &Bytes) -> Result<CacheHit, Error> {
+    /// Get an embed from the cache, where `version` is of the extractor that would be used on a miss.
+    pub async fn get(&self, key: &Bytes, version: &ExtractorVersion) -> Result<CacheHit, Error> {
         if let Some(occ) = self.pending.get_async(key).await {
             if !occ.get().is_closed() {
                 return Ok(CacheHit::Pending(occ.get().subscribe()));
@@ -149,9 +369,13 @@ impl EmbedCache {
         let now = Timestamp::now_utc();
 
         match entry {
-            CacheEntry::Occupied(occ) => match occ.get() {
-                CacheState::Ready(e) if now <= e.0 => Ok(CacheHit::Hit(e.clone())),
-                CacheState::Errored(e) if now <= e.expires => Err(Error::CacheError(e.clone())),
+            CacheEntry::Occupied(occ) => match occ.get().state {
+                CacheState::Ready(ref e) if now <= e.0 => {
+                    occ.get().touch();
+
+                    Ok(CacheHit::Hit(e.clone()))
+                }
+                CacheState::Errored(ref e) if now <= e.expires => Err(Error::CacheError(e.clone())),
                 _ => {
                     let (tx, rx) = match self.pending.entry_async(key.clone()).await {
                         scc::hash_index::Entry::Occupied(pending) => {
@@ -169,7 +393,7 @@ impl EmbedCache {
                         }
                     };
 
-                    _ = occ.remove_entry(); // remove + unlock bucket here
+                    _ = self.cache.remove(occ); // remove + unlock bucket here
 
                     Ok(CacheHit::Miss(CacheMiss { tx, rx }))
                 }
@@ -198,11 +422,12 @@ impl EmbedCache {
 
                 tracing::debug!("Cache miss: {:?}", key.clone());
 
-                match self.get_tiered(key.clone(), now).await? {
-                    Some(embed) => {
+                match self.get_tiered(key.clone(), now, version).await {
+                    Some(CachedEmbed { embed, .. }) => {
                         let state = CacheState::Ready(embed.clone());
 
-                        _ = self.cache.put_async(key, state.clone()).await;
+                        self.cache.put_async(key, self.for_memory(&state, now)).await;
+                        self.cache.evict().await;
 
                         tx.send_replace(Some(state));
 
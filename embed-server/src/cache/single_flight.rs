@@ -0,0 +1,184 @@
+//! Distributed single-flight across instances, using a redis `SET NX PX` lease
+//!
+//! On a cache miss, an instance must acquire the lease for that URL before fetching it. Instances that
+//! lose the race wait until they're notified through the invalidation bus, or poll the shared tiers,
+//! until the embed appears or the lease is released without one. Waiting is limited to the lease duration,
+//! after which the instance fetches it itself rather than waiting on other instances indefinitely.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use std::{hash::BuildHasher, sync::Arc};
+
+use bytes::Bytes;
+use embed::timestamp::Timestamp;
+use fred::{
+    clients::Client,
+    interfaces::{ClientLike as _, KeysInterface as _, LuaInterface as _},
+    types::{config::Config, Expiration, SetOptions},
+};
+use hashbrown::HashMap;
+use tokio::sync::Notify;
+
+use super::{storage::ExtractorVersion, EmbedCache, Lease, SingleFlight};
+use crate::{config::ConfigError, error::Error};
+
+/// Only delete the lock if it's still held by this token
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+else
+    return 0
+end
+"#;
+
+const LOCK_PREFIX: &[u8] = b"embed-lock:";
+
+pub struct DistributedLock {
+    client: Client,
+    lease: Duration,
+    poll: Duration,
+
+    seed: ahash::RandomState,
+    counter: AtomicU64,
+
+    /// Notified by the invalidation bus when another instance has updated the key
+    waiters: scc::HashMap<Bytes, Arc<Notify>, ahash::RandomState>,
+}
+
+impl DistributedLock {
+    /// Create the lock from the `[cache.redis]` config, if `single_flight_lease_ms` is set
+    pub fn create(config: &HashMap<String, String>) -> Result<Option<Self>, Error> {
+        let Some(lease) = config.get("single_flight_lease_ms") else {
+            return Ok(None);
+        };
+
+        let Ok(lease) = lease.parse() else {
+            return Err(Error::ConfigError(ConfigError::InvalidCacheField(
+                "redis.single_flight_lease_ms",
+            )));
+        };
+
+        let mut poll = 250;
+
+        if let Some(poll_ms) = config.get("single_flight_poll_ms") {
+            let Ok(poll_ms) = poll_ms.parse() else {
+                return Err(Error::ConfigError(ConfigError::InvalidCacheField(
+                    "redis.single_flight_poll_ms",
+                )));
+            };
+
+            poll = poll_ms;
+        }
+
+        let Some(url) = config.get("url") else {
+            return Err(Error::ConfigError(ConfigError::MissingCacheField("redis.url")));
+        };
+
+        let client = Client::new(Config::from_url(url)?, None, None, None);
+
+        client.connect();
+
+        Ok(Some(DistributedLock {
+            client,
+            lease: Duration::from_millis(lease),
+            poll: Duration::from_millis(poll),
+            seed: ahash::RandomState::new(),
+            counter: AtomicU64::new(0),
+            waiters: scc::HashMap::default(),
+        }))
+    }
+
+    fn lock_key(key: &Bytes) -> Bytes {
+        let mut lock_key = Vec::with_capacity(LOCK_PREFIX.len() + key.len());
+
+        lock_key.extend_from_slice(LOCK_PREFIX);
+        lock_key.extend_from_slice(key);
+
+        Bytes::from(lock_key)
+    }
+
+    async fn try_acquire(&self, key: &Bytes) -> Result<Option<Lease>, Error> {
+        let lock_key = Self::lock_key(key);
+        let token = format!(
+            "{:016x}",
+            self.seed.hash_one(self.counter.fetch_add(1, Ordering::Relaxed))
+        );
+
+        let res = self
+            .client
+            .set::<Option<String>, _, _>(
+                lock_key.clone(),
+                token.as_str(),
+                Some(Expiration::PX(self.lease.as_millis() as i64)),
+                Some(SetOptions::NX),
+                false,
+            )
+            .await?;
+
+        Ok(res.map(|_| Lease {
+            held: Some((lock_key, token)),
+        }))
+    }
+
+    pub async fn release(&self, lease: Lease) {
+        let Some((lock_key, token)) = lease.held else {
+            return;
+        };
+
+        if let Err(e) = self.client.eval::<i64, _, _, _>(RELEASE_SCRIPT, lock_key, token).await {
+            log::error!("Error releasing single-flight lease: {e:?}");
+        }
+    }
+
+    /// Wake any local waiter for this key
+    pub async fn notify(&self, key: &Bytes) {
+        self.waiters.read_async(key, |_, notify| notify.notify_one()).await;
+    }
+}
+
+impl EmbedCache {
+    pub(super) async fn single_flight_distributed(
+        &self,
+        lock: &DistributedLock,
+        key: &Bytes,
+        version: &ExtractorVersion,
+    ) -> SingleFlight {
+        let notify = Arc::new(Notify::new());
+
+        // NOTE: the pending index ensures there's only one local waiter per key
+        _ = lock.waiters.upsert_async(key.clone(), notify.clone()).await;
+
+        let deadline = tokio::time::Instant::now() + lock.lease;
+
+        let res = loop {
+            match lock.try_acquire(key).await {
+                Ok(Some(lease)) => break SingleFlight::Fetch(lease),
+                Ok(None) => {}
+                Err(e) => {
+                    // degrade to fetching it ourselves
+                    log::error!("Error acquiring single-flight lease: {e:?}");
+                    break SingleFlight::Fetch(Lease::default());
+                }
+            }
+
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+
+            if remaining.is_zero() {
+                log::debug!("Gave up waiting on single-flight lease, fetching locally");
+                break SingleFlight::Fetch(Lease::default());
+            }
+
+            // another instance is fetching it, wait for a notification or poll
+            _ = tokio::time::timeout(lock.poll.min(remaining), notify.notified()).await;
+
+            // if not found, try to acquire the lease again, in case the other instance failed
+            if let Some(embed) = self.get_tiered(key.clone(), Timestamp::now_utc(), version).await {
+                break SingleFlight::Fetched(embed.embed);
+            }
+        };
+
+        lock.waiters.remove_async(key).await;
+
+        res
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use hashbrown::HashMap;
+use reqwest::StatusCode;
+use tokio::sync::mpsc;
+
+use crate::config::ConfigError;
+
+use super::{Bytes, CachedEmbed, Error};
+
+const DEFAULT_BATCH_SIZE: usize = 256;
+
+pub enum WriteOp {
+    Put(Bytes, CachedEmbed),
+    Del(Bytes),
+}
+
+enum Message {
+    Write(WriteOp),
+    Shutdown,
+}
+
+/// Write-behind queue for storage backends that block on writes.
+///
+/// Writes are sent to a single dedicated thread, which commits everything queued
+/// at that point (up to the batch size) in one transaction.
+#[derive(Debug, Clone)]
+pub struct WriteBehind {
+    tx: mpsc::Sender<Message>,
+    thread: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+/// Parse the `write_batch_size` field of a cache config, where `field` is the full name for errors
+pub fn batch_size(config: &HashMap<String, String>, field: &'static str) -> Result<usize, Error> {
+    match config.get("write_batch_size") {
+        None => Ok(DEFAULT_BATCH_SIZE),
+        Some(size) => match size.parse() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(Error::ConfigError(ConfigError::InvalidCacheField(field))),
+        },
+    }
+}
+
+impl WriteBehind {
+    /// Spawn the writer thread, which calls `commit` with each batch of writes.
+    ///
+    /// `commit` should drain the batch, and any error is logged but otherwise discards the batch.
+    pub fn spawn<F>(name: &str, batch_size: usize, mut commit: F) -> Result<Self, Error>
+    where
+        F: FnMut(&mut Vec<WriteOp>) -> Result<(), Error> + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel(batch_size * 4);
+
+        let thread = std::thread::Builder::new().name(name.to_owned()).spawn(move || {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut running = true;
+
+            while running {
+                let Some(msg) = rx.blocking_recv() else {
+                    break;
+                };
+
+                let mut next = Some(msg);
+
+                // take everything else already queued, up to the batch size
+                while let Some(msg) = next.take().or_else(|| rx.try_recv().ok()) {
+                    match msg {
+                        Message::Write(op) => batch.push(op),
+                        Message::Shutdown => {
+                            running = false;
+                            break;
+                        }
+                    }
+
+                    if batch.len() >= batch_size {
+                        break;
+                    }
+                }
+
+                if batch.is_empty() {
+                    continue;
+                }
+
+                log::trace!("Committing {} cache writes", batch.len());
+
+                if let Err(e) = commit(&mut batch) {
+                    log::error!("Error committing cache writes: {e:?}");
+                }
+
+                batch.clear();
+            }
+        })?;
+
+        Ok(WriteBehind {
+            tx,
+            thread: Arc::new(Mutex::new(Some(thread))),
+        })
+    }
+
+    /// Queue a write, waiting only if the queue is full
+    pub async fn write(&self, op: WriteOp) -> Result<(), Error> {
+        match self.tx.send(Message::Write(op)).await {
+            Ok(()) => Ok(()),
+            Err(_) => Err(Error::Failure(StatusCode::SERVICE_UNAVAILABLE)),
+        }
+    }
+
+    /// Commit any queued writes and stop the writer thread
+    pub async fn shutdown(&self) {
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+
+        _ = self.tx.send(Message::Shutdown).await;
+
+        if !matches!(
+            tokio::task::spawn_blocking(move || thread.join()).await,
+            Ok(Ok(()))
+        ) {
+            log::error!("Cache writer thread panicked");
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use hashbrown::HashMap;
+use triomphe::Arc;
+
+use crate::config::ConfigError;
+
+use super::{
+    Bytes, Cache, CacheFactory, CacheRecord, CacheStorage, CachedEmbed, Error, ScanEntry, Sender, Timestamp,
+};
+
+/// Stores each embed as a plain JSON [`CacheRecord`], in a directory tree sharded by the blake3 hash of the key.
+///
+/// The file's modification time is set to the embed's expiration, so expired entries can be
+/// found with standard tools (e.g. `find -newermt`) without parsing the files.
+#[derive(Debug, Clone)]
+pub struct FilesystemCache {
+    root: Arc<PathBuf>,
+}
+
+impl CacheFactory for FilesystemCache {
+    fn create(config: &HashMap<String, String>) -> Result<Cache, Error> {
+        let Some(path) = config.get("path") else {
+            return Err(Error::ConfigError(ConfigError::MissingCacheField(
+                "filesystem.path",
+            )));
+        };
+
+        Self::open(path).map(Cache::Filesystem)
+    }
+}
+
+/// Converts a timestamp to the equivalent `SystemTime`, for use as a file modification time
+fn to_system_time(ts: Timestamp) -> SystemTime {
+    let ms = ts.duration_since(Timestamp::UNIX_EPOCH).whole_milliseconds();
+
+    SystemTime::UNIX_EPOCH + Duration::from_millis(ms.max(0) as u64)
+}
+
+fn write_atomic(tmp: &Path, path: &Path, contents: &[u8], expires: SystemTime) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(tmp)?;
+
+    file.write_all(contents)?;
+    file.set_modified(expires)?;
+    file.sync_all()?;
+
+    drop(file);
+
+    std::fs::rename(tmp, path)
+}
+
+impl FilesystemCache {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = path.as_ref().to_path_buf();
+
+        std::fs::create_dir_all(&root)?;
+
+        Ok(FilesystemCache { root: Arc::new(root) })
+    }
+
+    /// `{root}/ab/cd/abcd...ef.json`
+    fn path_for(&self, key: &[u8]) -> PathBuf {
+        let hash = blake3::hash(key).to_hex();
+
+        let mut path = self.root.join(&hash[0..2]);
+        path.push(&hash[2..4]);
+        path.push(format!("{hash}.json"));
+        path
+    }
+
+    fn get_blocking(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
+        let path = self.path_for(&key);
+
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // expired, clean it up while we're here
+        if metadata.modified()? < to_system_time(now) {
+            return self.del_blocking(key).map(|_| None);
+        }
+
+        let CacheRecord { embed, .. } = match std::fs::read_to_string(&path) {
+            Ok(json) => json_impl::from_str(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if embed.expires() < now {
+            return Ok(None);
+        }
+
+        Ok(Some(embed))
+    }
+
+    fn put_blocking(&self, _now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
+        static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = self.path_for(&key);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let expires = value.expires();
+
+        let json = json_impl::to_string(&CacheRecord {
+            url: String::from_utf8_lossy(&key).into_owned(),
+            embed: value,
+        })?;
+
+        // write to a unique temporary file in the same directory, then rename over the
+        // real file so readers never observe a partially written embed
+        let tmp = path.with_extension(format!(
+            "json.{}.{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        if let Err(e) = write_atomic(&tmp, &path, json.as_bytes(), to_system_time(expires)) {
+            _ = std::fs::remove_file(&tmp);
+
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    fn del_blocking(&self, key: Bytes) -> Result<(), Error> {
+        match std::fs::remove_file(self.path_for(&key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn scan_blocking(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+        for a in std::fs::read_dir(&*self.root)? {
+            for b in std::fs::read_dir(a?.path())? {
+                for file in std::fs::read_dir(b?.path())? {
+                    let path = file?.path();
+
+                    // skip temporary files
+                    if path.extension().is_none_or(|ext| ext != "json") {
+                        continue;
+                    }
+
+                    let json = match std::fs::read_to_string(&path) {
+                        Ok(json) => json,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                        Err(e) => return Err(e.into()),
+                    };
+
+                    let record: CacheRecord = match json_impl::from_str(&json) {
+                        Ok(record) => record,
+                        Err(e) => {
+                            log::warn!("Skipping invalid cache file {}: {e}", path.display());
+                            continue;
+                        }
+                    };
+
+                    let entry = ScanEntry {
+                        key: Bytes::from(record.url),
+                        size: json.len(),
+                        embed: record.embed,
+                    };
+
+                    if tx.blocking_send(entry).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CacheStorage for FilesystemCache {
+    async fn get(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.get_blocking(now, key))
+            .await
+            .expect("Unable to execute blocking task")
+    }
+
+    async fn put(&self, now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.put_blocking(now, key, value))
+            .await
+            .expect("Unable to execute blocking task")
+    }
+
+    async fn del(&self, key: Bytes) -> Result<(), Error> {
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.del_blocking(key))
+            .await
+            .expect("Unable to execute blocking task")
+    }
+
+    async fn scan(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.scan_blocking(tx))
+            .await
+            .expect("Unable to execute blocking task")
+    }
+}
@@ -1,6 +1,8 @@
 use bytes::Bytes;
 use embed::{timestamp::Timestamp, EmbedWithExpire};
 use hashbrown::HashMap;
+use smol_str::SmolStr;
+use tokio::sync::mpsc::Sender;
 use triomphe::Arc;
 
 #[cfg(feature = "cache_redis")]
@@ -12,9 +14,138 @@ pub mod sqlite;
 #[cfg(feature = "cache_redb")]
 pub mod redb;
 
-use crate::error::Error;
+#[cfg(feature = "cache_fs")]
+pub mod filesystem;
 
-pub type CachedEmbed = Arc<EmbedWithExpire>;
+#[cfg(any(feature = "cache_rusqlite", feature = "cache_redb"))]
+pub mod batch;
+
+use crate::{error::Error, extractors::Extractor};
+
+/// Name and version of the extractor that produced a cached embed
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ExtractorVersion {
+    #[serde(rename = "n")]
+    pub name: SmolStr,
+    #[serde(rename = "v")]
+    pub version: u32,
+}
+
+impl ExtractorVersion {
+    pub fn of(extractor: &dyn Extractor) -> Self {
+        ExtractorVersion {
+            name: SmolStr::new_static(extractor.name()),
+            version: extractor.version(),
+        }
+    }
+
+    /// An entry is stale if it was produced by another extractor,
+    /// or an older version of the current extractor.
+    pub fn is_stale(&self, current: &ExtractorVersion) -> bool {
+        self.name != current.name || self.version < current.version
+    }
+}
+
+/// Embed as stored in cache storage backends, tagged with the extractor version that produced it.
+///
+/// Serialized as `[expires, embed, version]`. Entries from before versioning are missing the version,
+/// so default to an empty extractor name and will always be considered stale.
+#[derive(Debug, Clone)]
+pub struct CachedEmbed {
+    pub embed: Arc<EmbedWithExpire>,
+    pub version: ExtractorVersion,
+}
+
+impl CachedEmbed {
+    #[inline]
+    pub fn expires(&self) -> Timestamp {
+        self.embed.0
+    }
+}
+
+const _: () = {
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess};
+    use serde::ser::{Serialize, Serializer};
+
+    impl Serialize for CachedEmbed {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            (&self.embed.0, &self.embed.1, &self.version).serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for CachedEmbed {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            return deserializer.deserialize_seq(Visitor);
+
+            struct Visitor;
+
+            impl<'de> de::Visitor<'de> for Visitor {
+                type Value = CachedEmbed;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("A sequence of expiration, embed and optional extractor version")
+                }
+
+                fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                where
+                    A: SeqAccess<'de>,
+                {
+                    let Some(expires) = seq.next_element()? else {
+                        return Err(de::Error::invalid_length(0, &self));
+                    };
+
+                    let Some(embed) = seq.next_element()? else {
+                        return Err(de::Error::invalid_length(1, &self));
+                    };
+
+                    Ok(CachedEmbed {
+                        embed: Arc::new((expires, embed)),
+                        version: seq.next_element()?.unwrap_or_default(),
+                    })
+                }
+            }
+        }
+    }
+};
+
+/// Embed along with the key it's stored under, as written by the filesystem tier
+/// and by `embed-server cache dump`, one per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheRecord {
+    pub url: String,
+    pub embed: CachedEmbed,
+}
+
+/// Entry visited by [`CacheStorage::scan`]
+pub struct ScanEntry {
+    pub key: Bytes,
+    /// Size of the stored entry, in bytes
+    pub size: usize,
+    pub embed: CachedEmbed,
+}
+
+impl ScanEntry {
+    /// Parse a stored entry, logging and skipping it if it's invalid
+    fn parse(key: Bytes, json: &str) -> Option<ScanEntry> {
+        match json_impl::from_str(json) {
+            Ok(embed) => Some(ScanEntry {
+                key,
+                size: json.len(),
+                embed,
+            }),
+            Err(e) => {
+                log::warn!("Skipping invalid cache entry {key:?}: {e}");
+                None
+            }
+        }
+    }
+}
 
 pub(crate) trait CacheFactory: Sized {
     fn create(config: &HashMap<String, String>) -> Result<Cache, Error>;
@@ -25,13 +156,18 @@ pub(crate) trait CacheStorage: Sized {
     async fn put(&self, now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error>;
     async fn del(&self, key: Bytes) -> Result<(), Error>;
 
+    /// Send every entry in storage, including expired entries, until the receiver is dropped.
+    ///
+    /// This is intended for administration only, and may be slow for large caches.
+    async fn scan(&self, tx: Sender<ScanEntry>) -> Result<(), Error>;
+
     async fn shutdown(self) -> Result<(), Error> {
         Ok(())
     }
 }
 
 macro_rules! impl_cache {
-    ($($(#[$meta:meta])* $name:ident => $inner:ty),*) => {
+    ($($(#[$meta:meta])* $name:ident($config:literal) => $inner:ty),*) => {
         pub enum Cache {
             $($(#[$meta])* $name($inner)),*
         }
@@ -65,6 +201,13 @@ macro_rules! impl_cache {
                 }
             }
 
+            async fn scan(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+                match self {
+                    $($(#[$meta])* Cache::$name(inner) => inner.scan(tx).await,)*
+                    _ => Ok(()),
+                }
+            }
+
             async fn shutdown(self) -> Result<(), Error> {
                 match self {
                     $($(#[$meta])* Cache::$name(inner) => inner.shutdown().await,)*
@@ -72,6 +215,33 @@ macro_rules! impl_cache {
                 }
             }
         }
+
+        impl CacheNameInner {
+            pub fn create(self, config: &HashMap<String, String>) -> Result<Cache, Error> {
+                match self {
+                    $($(#[$meta])* CacheNameInner::$name => <$inner as CacheFactory>::create(config),)*
+                }
+            }
+
+            /// Name of the tier as used in the config, e.g. `redb` for `[cache.redb]`
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($(#[$meta])* CacheNameInner::$name => $config,)*
+                }
+            }
+        }
+    }
+}
+
+impl Cache {
+    /// Shared storage is visible to every instance, rather than being local to this one
+    #[allow(unreachable_patterns)]
+    pub fn is_shared(&self) -> bool {
+        match self {
+            #[cfg(feature = "cache_redis")]
+            Cache::Redis(_) => true,
+            _ => false,
+        }
     }
 }
 
@@ -102,11 +272,51 @@ const _: () = {
 
 impl_cache! {
     #[cfg(feature = "cache_redis")]
-    Redis => redis::RedisCache,
+    Redis("redis") => redis::RedisCache,
 
     #[cfg(feature = "cache_rusqlite")]
-    Sqlite => sqlite::SqliteCache,
+    Sqlite("sqlite") => sqlite::SqliteCache,
 
     #[cfg(feature = "cache_redb")]
-    Redb => redb::RedbCache
+    Redb("redb") => redb::RedbCache,
+
+    #[cfg(feature = "cache_fs")]
+    Filesystem("filesystem") => filesystem::FilesystemCache
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_embed_versions() {
+        let legacy = r#"["2023-05-17T19:24:45.455Z",{"v":"1","ts":"2023-05-17T19:09:45.455Z","ty":"link"}]"#;
+        let legacy: CachedEmbed = json_impl::from_str(legacy).unwrap();
+
+        let current = ExtractorVersion {
+            name: SmolStr::new_static("generic"),
+            version: 1,
+        };
+
+        assert_eq!(legacy.version, ExtractorVersion::default());
+        assert!(legacy.version.is_stale(&current));
+
+        let versioned = CachedEmbed {
+            embed: legacy.embed.clone(),
+            version: current.clone(),
+        };
+
+        let versioned: CachedEmbed = json_impl::from_str(&json_impl::to_string(&versioned).unwrap()).unwrap();
+
+        assert_eq!(versioned.version, current);
+        assert_eq!(versioned.expires(), legacy.expires());
+        assert!(!versioned.version.is_stale(&current));
+
+        let newer = ExtractorVersion {
+            version: 2,
+            ..current
+        };
+
+        assert!(versioned.version.is_stale(&newer));
+    }
 }
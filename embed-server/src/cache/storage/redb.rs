@@ -1,11 +1,18 @@
 use hashbrown::HashMap;
+use redb::ReadableTable as _;
+use triomphe::Arc;
 
 use crate::config::ConfigError;
 
-use super::{Bytes, Cache, CacheFactory, CacheStorage, CachedEmbed, Error, Timestamp};
+use super::batch::{batch_size, WriteBehind, WriteOp};
+use super::{Bytes, Cache, CacheFactory, CacheStorage, CachedEmbed, Error, ScanEntry, Sender, Timestamp};
 
+/// Reads run on the blocking thread pool, while writes are batched through
+/// a single writer thread and committed many at a time.
+#[derive(Clone)]
 pub struct RedbCache {
-    db: redb::Database,
+    db: Arc<redb::Database>,
+    writer: WriteBehind,
     compact_on_shutdown: bool,
 }
 
@@ -40,7 +47,7 @@ impl CacheFactory for RedbCache {
         let db = builder.create(path)?;
 
         {
-            println!("Creating table");
+            log::debug!("Creating table");
 
             // Create the table if it doesn't exist
             let w = db.begin_write()?;
@@ -48,15 +55,28 @@ impl CacheFactory for RedbCache {
             w.commit()?;
         }
 
+        let db = Arc::new(db);
+
+        let writer = WriteBehind::spawn(
+            "redb-cache-writer",
+            batch_size(config, "redb.write_batch_size")?,
+            {
+                let db = db.clone();
+
+                move |batch| RedbCache::commit_blocking(&db, batch)
+            },
+        )?;
+
         Ok(Cache::Redb(RedbCache {
             db,
+            writer,
             compact_on_shutdown,
         }))
     }
 }
 
-impl CacheStorage for RedbCache {
-    async fn get(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
+impl RedbCache {
+    fn get_blocking(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
         let t = self.db.begin_read()?.open_table(EMBEDS_TABLE)?;
 
         let Some(embed) = t.get(key.as_ref())? else {
@@ -65,38 +85,105 @@ impl CacheStorage for RedbCache {
 
         let embed: CachedEmbed = json_impl::from_str(&embed.value())?;
 
-        if embed.0 < now {
+        if embed.expires() < now {
             return Ok(None);
         }
 
         Ok(Some(embed))
     }
 
-    async fn put(&self, _now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
-        let json = json_impl::to_string(&value)?;
-
-        let w = self.db.begin_write()?;
+    fn commit_blocking(db: &redb::Database, batch: &mut Vec<WriteOp>) -> Result<(), Error> {
+        let w = db.begin_write()?;
 
-        w.open_table(EMBEDS_TABLE)?.insert(key.as_ref(), json)?;
+        {
+            let mut t = w.open_table(EMBEDS_TABLE)?;
+
+            for op in batch.drain(..) {
+                match op {
+                    WriteOp::Put(key, value) => {
+                        let json = match json_impl::to_string(&value) {
+                            Ok(json) => json,
+                            Err(e) => {
+                                log::error!("Error serializing embed for {key:?}: {e}");
+                                continue;
+                            }
+                        };
+
+                        t.insert(key.as_ref(), json)?;
+                    }
+                    WriteOp::Del(key) => {
+                        t.remove(key.as_ref())?;
+                    }
+                }
+            }
+        }
 
         w.commit()?;
 
         Ok(())
     }
 
-    async fn del(&self, key: Bytes) -> Result<(), Error> {
-        let w = self.db.begin_write()?;
+    fn scan_blocking(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+        let t = self.db.begin_read()?.open_table(EMBEDS_TABLE)?;
 
-        w.open_table(EMBEDS_TABLE)?.remove(key.as_ref())?;
+        for row in t.iter()? {
+            let (key, embed) = row?;
 
-        w.commit()?;
+            let Some(entry) = ScanEntry::parse(Bytes::copy_from_slice(key.value()), &embed.value()) else {
+                continue;
+            };
+
+            if tx.blocking_send(entry).is_err() {
+                break;
+            }
+        }
 
         Ok(())
     }
+}
 
-    async fn shutdown(mut self) -> Result<(), Error> {
-        if self.compact_on_shutdown {
-            self.db.compact()?;
+impl CacheStorage for RedbCache {
+    async fn get(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.get_blocking(now, key))
+            .await
+            .expect("Unable to execute blocking task")
+    }
+
+    async fn put(&self, _now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
+        self.writer.write(WriteOp::Put(key, value)).await
+    }
+
+    async fn del(&self, key: Bytes) -> Result<(), Error> {
+        self.writer.write(WriteOp::Del(key)).await
+    }
+
+    async fn scan(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.scan_blocking(tx))
+            .await
+            .expect("Unable to execute blocking task")
+    }
+
+    async fn shutdown(self) -> Result<(), Error> {
+        let RedbCache {
+            mut db,
+            writer,
+            compact_on_shutdown,
+        } = self;
+
+        // the writer thread holds its own reference to the database
+        writer.shutdown().await;
+
+        if compact_on_shutdown {
+            match Arc::get_mut(&mut db) {
+                Some(db) => {
+                    db.compact()?;
+                }
+                None => log::warn!("Unable to compact redb cache, database still in use"),
+            }
         }
 
         Ok(())
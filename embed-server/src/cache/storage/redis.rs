@@ -1,3 +1,4 @@
+use futures_util::StreamExt;
 use hashbrown::HashMap;
 
 use fred::{
@@ -7,7 +8,7 @@ use fred::{
 
 use crate::config::ConfigError;
 
-use super::{Bytes, Cache, CacheFactory, CacheStorage, CachedEmbed, Error, Timestamp};
+use super::{Bytes, Cache, CacheFactory, CacheStorage, CachedEmbed, Error, ScanEntry, Sender, Timestamp};
 
 pub struct RedisCache {
     client: fred::clients::Client,
@@ -33,7 +34,7 @@ impl CacheStorage for RedisCache {
 
         let embed: CachedEmbed = json_impl::from_str(&json)?;
 
-        if embed.0 < now {
+        if embed.expires() < now {
             return Ok(None);
         }
 
@@ -42,7 +43,7 @@ impl CacheStorage for RedisCache {
 
     async fn put(&self, _now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
         let json = json_impl::to_string(&value)?;
-        let expires = value.0.duration_since(Timestamp::UNIX_EPOCH).whole_milliseconds() as i64;
+        let expires = value.expires().duration_since(Timestamp::UNIX_EPOCH).whole_milliseconds() as i64;
 
         self.client.set::<(), _, _>(key, json, Some(Expiration::PXAT(expires)), None, false).await?;
 
@@ -54,4 +55,28 @@ impl CacheStorage for RedisCache {
 
         Ok(())
     }
+
+    async fn scan(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+        // embeds are keyed by their URL, which skips anything else in the same database
+        let mut keys = std::pin::pin!(self.client.scan_buffered("http*", Some(100), None));
+
+        while let Some(key) = keys.next().await {
+            let key = key?;
+
+            // may have expired since scanning
+            let Some(json) = self.client.get::<Option<String>, _>(key.clone()).await? else {
+                continue;
+            };
+
+            let Some(entry) = ScanEntry::parse(Bytes::copy_from_slice(key.as_bytes()), &json) else {
+                continue;
+            };
+
+            if tx.send(entry).await.is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -2,11 +2,17 @@ use hashbrown::HashMap;
 
 use crate::config::ConfigError;
 
-use super::{Bytes, Cache, CacheFactory, CacheStorage, CachedEmbed, Error, Timestamp};
+use super::batch::{batch_size, WriteBehind, WriteOp};
+use super::{Bytes, Cache, CacheFactory, CacheStorage, CachedEmbed, Error, ScanEntry, Sender, Timestamp};
 
+type Pool = r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>;
+
+/// Reads use the connection pool on the blocking thread pool,
+/// while writes are batched through a single writer thread.
 #[derive(Debug, Clone)]
 pub struct SqliteCache {
-    pool: r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>,
+    pool: Pool,
+    writer: WriteBehind,
 }
 
 impl CacheFactory for SqliteCache {
@@ -15,12 +21,12 @@ impl CacheFactory for SqliteCache {
             return Err(Error::ConfigError(ConfigError::MissingCacheField("sqlite.path")));
         };
 
-        Self::open(path).map(Cache::Sqlite)
+        Self::open(path, batch_size(config, "sqlite.write_batch_size")?).map(Cache::Sqlite)
     }
 }
 
 impl SqliteCache {
-    pub fn open(path: &str) -> Result<Self, Error> {
+    pub fn open(path: &str, batch_size: usize) -> Result<Self, Error> {
         let manager = r2d2_sqlite::SqliteConnectionManager::file(path);
         let pool = r2d2::Pool::new(manager)?;
 
@@ -36,7 +42,13 @@ impl SqliteCache {
         "#,
         )?;
 
-        Ok(SqliteCache { pool })
+        let writer = WriteBehind::spawn("sqlite-cache-writer", batch_size, {
+            let pool = pool.clone();
+
+            move |batch| Self::commit_blocking(&pool, batch)
+        })?;
+
+        Ok(SqliteCache { pool, writer })
     }
 
     fn get_blocking(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
@@ -58,32 +70,68 @@ impl SqliteCache {
         };
 
         // expired
-        if matches!(embed, Some(ref e) if e.0 < now) {
+        if matches!(embed, Some(ref e) if e.expires() < now) {
             return Ok(None);
         }
 
         Ok(embed)
     }
 
-    fn put_blocking(&self, _now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
-        let hash = blake3::hash(key.as_ref());
+    fn commit_blocking(pool: &Pool, batch: &mut Vec<WriteOp>) -> Result<(), Error> {
+        let mut db = pool.get()?;
+        let tx = db.transaction()?;
+
+        {
+            let mut put = tx.prepare_cached(
+                r"INSERT INTO embeds (hash, url, embed) VALUES (?, ?, ?)
+                ON CONFLICT(hash) DO UPDATE SET embed = excluded.embed",
+            )?;
+
+            let mut del = tx.prepare_cached("DELETE FROM embeds WHERE hash = ? AND url = ?")?;
+
+            for op in batch.drain(..) {
+                match op {
+                    WriteOp::Put(key, value) => {
+                        let json = match json_impl::to_string(&value) {
+                            Ok(json) => json,
+                            Err(e) => {
+                                log::error!("Error serializing embed for {key:?}: {e}");
+                                continue;
+                            }
+                        };
+
+                        put.execute((blake3::hash(&key).as_bytes(), key.as_ref(), json))?;
+                    }
+                    WriteOp::Del(key) => {
+                        del.execute([blake3::hash(&key).as_bytes(), key.as_ref()])?;
+                    }
+                }
+            }
+        }
 
-        self.pool.get()?.execute(
-            r"INSERT INTO embeds (hash, url, embed) VALUES (?, ?, ?)
-            ON CONFLICT(hash) DO UPDATE SET embed = excluded.embed",
-            (hash.as_bytes(), key.as_ref(), json_impl::to_string(&value)?),
-        )?;
+        tx.commit()?;
 
         Ok(())
     }
 
-    fn del_blocking(&self, key: Bytes) -> Result<(), Error> {
-        let hash = blake3::hash(key.as_ref());
+    fn scan_blocking(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
+        let db = self.pool.get()?;
 
-        self.pool.get()?.execute(
-            "DELETE FROM embeds WHERE hash = ? AND url = ?",
-            [hash.as_bytes(), key.as_ref()],
-        )?;
+        let mut q = db.prepare("SELECT CAST(url AS BLOB), embed FROM embeds")?;
+        let mut rows = q.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key: Vec<u8> = row.get(0)?;
+            let embed: String = row.get(1)?;
+
+            let Some(entry) = ScanEntry::parse(Bytes::from(key), &embed) else {
+                continue;
+            };
+
+            if tx.blocking_send(entry).is_err() {
+                break;
+            }
+        }
 
         Ok(())
     }
@@ -98,19 +146,25 @@ impl CacheStorage for SqliteCache {
             .expect("Unable to execute blocking task")
     }
 
-    async fn put(&self, now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
-        let this = self.clone();
-
-        tokio::task::spawn_blocking(move || this.put_blocking(now, key, value))
-            .await
-            .expect("Unable to execute blocking task")
+    async fn put(&self, _now: Timestamp, key: Bytes, value: CachedEmbed) -> Result<(), Error> {
+        self.writer.write(WriteOp::Put(key, value)).await
     }
 
     async fn del(&self, key: Bytes) -> Result<(), Error> {
+        self.writer.write(WriteOp::Del(key)).await
+    }
+
+    async fn scan(&self, tx: Sender<ScanEntry>) -> Result<(), Error> {
         let this = self.clone();
 
-        tokio::task::spawn_blocking(move || this.del_blocking(key))
+        tokio::task::spawn_blocking(move || this.scan_blocking(tx))
             .await
             .expect("Unable to execute blocking task")
     }
+
+    async fn shutdown(self) -> Result<(), Error> {
+        self.writer.shutdown().await;
+
+        Ok(())
+    }
 }
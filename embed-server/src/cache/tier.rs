@@ -0,0 +1,267 @@
+//! Storage tiers wrapped in a circuit breaker, so an unavailable cache only costs performance.
+//!
+//! After `breaker_threshold` consecutive errors or timeouts the tier is skipped for `breaker_cooldown_ms`,
+//! then a single probe request is let through. If it succeeds the tier is used again, otherwise it's
+//! skipped for another cooldown period.
+//!
+//! Each tier also has a [`Policy`] for which operations it's used for.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use embed::timestamp::{Duration as TimestampDuration, Timestamp};
+use hashbrown::HashMap;
+use reqwest::StatusCode;
+
+use super::cap_ttl;
+use super::storage::{Cache, CacheNameInner, CacheStorage, CachedEmbed};
+use crate::{config::ConfigError, error::Error};
+
+#[rustfmt::skip]
+mod defaults {
+    pub const TIMEOUT_MS: u64 = 1000;
+    pub const THRESHOLD: u32 = 5;
+    pub const COOLDOWN_MS: u64 = 30_000;
+}
+
+pub struct Tier {
+    pub name: CacheNameInner,
+    storage: Cache,
+    policy: Policy,
+    health: Health,
+}
+
+/// Configured with `mode`, `backfill` and `max_ttl` alongside the other tier options
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Used for reads, unless `mode = "write_only"`
+    pub read: bool,
+    /// Used for writes and deletes, unless `mode = "read_only"`
+    pub write: bool,
+    /// Written to when an embed is found in a later tier, unless `backfill = "false"`
+    pub backfill: bool,
+    /// Entries are stored for at most this long, regardless of their expiration
+    pub max_ttl: Option<TimestampDuration>,
+}
+
+impl Policy {
+    pub fn create(name: CacheNameInner, config: &HashMap<String, String>) -> Result<Policy, Error> {
+        let (read, write) = match config.get("mode").map(String::as_str) {
+            None | Some("read_write") => (true, true),
+            Some("read_only") => (true, false),
+            Some("write_only") => (false, true),
+            Some(_) => {
+                return Err(Error::ConfigError(ConfigError::InvalidTierField(
+                    name.name(),
+                    "mode",
+                )))
+            }
+        };
+
+        let max_ttl = match config.get("max_ttl") {
+            None => None,
+            Some(secs) => match secs.parse::<i64>() {
+                Ok(secs) if secs > 0 => Some(TimestampDuration::seconds(secs)),
+                _ => {
+                    return Err(Error::ConfigError(ConfigError::InvalidTierField(
+                        name.name(),
+                        "max_ttl",
+                    )))
+                }
+            },
+        };
+
+        Ok(Policy {
+            read,
+            write,
+            backfill: write && parse_field(name, config, "backfill", true)?,
+            max_ttl,
+        })
+    }
+}
+
+struct Health {
+    timeout: Duration,
+    threshold: u32,
+    cooldown: Duration,
+
+    epoch: Instant,
+
+    /// Consecutive failures
+    failures: AtomicU32,
+
+    /// Milliseconds since `epoch` until which the tier is skipped, or 0 when healthy
+    open_until: AtomicU64,
+
+    /// Milliseconds since `epoch` when a single request started probing the tier after the cooldown
+    probe_started: AtomicU64,
+}
+
+fn parse_field<T: std::str::FromStr>(
+    name: CacheNameInner,
+    config: &HashMap<String, String>,
+    field: &'static str,
+    default: T,
+) -> Result<T, Error> {
+    match config.get(field) {
+        None => Ok(default),
+        Some(value) => {
+            value.parse().map_err(|_| Error::ConfigError(ConfigError::InvalidTierField(name.name(), field)))
+        }
+    }
+}
+
+impl Health {
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+
+    /// Check if a request should be sent to the tier
+    fn allow(&self) -> bool {
+        let open_until = self.open_until.load(Ordering::Acquire);
+
+        if open_until == 0 {
+            return true;
+        }
+
+        let now = self.now();
+
+        if now < open_until {
+            return false;
+        }
+
+        let started = self.probe_started.load(Ordering::Acquire);
+
+        // cooldown elapsed, only let one request through to probe it,
+        // unless the last probe was cancelled before it could finish
+        if started != 0 && now < started + self.timeout.as_millis() as u64 * 2 {
+            return false;
+        }
+
+        self.probe_started.compare_exchange(started, now.max(1), Ordering::AcqRel, Ordering::Relaxed).is_ok()
+    }
+
+    fn success(&self, name: CacheNameInner) {
+        self.failures.store(0, Ordering::Relaxed);
+
+        if self.open_until.swap(0, Ordering::AcqRel) != 0 {
+            log::info!("Cache tier {} recovered", name.name());
+        }
+
+        self.probe_started.store(0, Ordering::Release);
+    }
+
+    fn failure(&self, name: CacheNameInner) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed).saturating_add(1);
+        let probing = self.probe_started.swap(0, Ordering::AcqRel) != 0;
+
+        if probing || failures >= self.threshold {
+            // never store 0, as that would close the breaker
+            let until = (self.now() + self.cooldown.as_millis() as u64).max(1);
+
+            self.open_until.store(until, Ordering::Release);
+
+            log::warn!(
+                failures,
+                "Skipping cache tier {} for {:?}",
+                name.name(),
+                self.cooldown
+            );
+        }
+    }
+}
+
+impl Tier {
+    pub fn create(
+        name: CacheNameInner,
+        storage: Cache,
+        config: &HashMap<String, String>,
+    ) -> Result<Tier, Error> {
+        Ok(Tier {
+            name,
+            storage,
+            policy: Policy::create(name, config)?,
+            health: Health {
+                timeout: Duration::from_millis(parse_field(
+                    name,
+                    config,
+                    "timeout_ms",
+                    defaults::TIMEOUT_MS,
+                )?),
+                threshold: parse_field(name, config, "breaker_threshold", defaults::THRESHOLD)?.max(1),
+                cooldown: Duration::from_millis(parse_field(
+                    name,
+                    config,
+                    "breaker_cooldown_ms",
+                    defaults::COOLDOWN_MS,
+                )?),
+                epoch: Instant::now(),
+                failures: AtomicU32::new(0),
+                open_until: AtomicU64::new(0),
+                probe_started: AtomicU64::new(0),
+            },
+        })
+    }
+
+    /// See [`Cache::is_shared`]
+    pub fn is_shared(&self) -> bool {
+        self.storage.is_shared()
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Run the operation with a timeout, recording the outcome.
+    /// Returns `default` without running it if the tier is being skipped.
+    async fn run<T>(&self, default: T, op: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        if !self.health.allow() {
+            return Ok(default);
+        }
+
+        let res = match tokio::time::timeout(self.health.timeout, op).await {
+            Ok(res) => res,
+            Err(_) => Err(Error::Failure(StatusCode::GATEWAY_TIMEOUT)),
+        };
+
+        match res {
+            Ok(_) => self.health.success(self.name),
+            Err(_) => self.health.failure(self.name),
+        }
+
+        res
+    }
+
+    pub async fn get(&self, now: Timestamp, key: Bytes) -> Result<Option<CachedEmbed>, Error> {
+        if !self.policy.read {
+            return Ok(None);
+        }
+
+        self.run(None, self.storage.get(now, key)).await
+    }
+
+    /// Store the embed, with its expiration capped to the `max_ttl` of this tier
+    pub async fn put(&self, now: Timestamp, key: Bytes, mut value: CachedEmbed) -> Result<(), Error> {
+        if !self.policy.write {
+            return Ok(());
+        }
+
+        value.embed = cap_ttl(&value.embed, now, self.policy.max_ttl);
+
+        self.run((), self.storage.put(now, key, value)).await
+    }
+
+    pub async fn del(&self, key: Bytes) -> Result<(), Error> {
+        if !self.policy.write {
+            return Ok(());
+        }
+
+        self.run((), self.storage.del(key)).await
+    }
+
+    pub async fn shutdown(self) -> Result<(), Error> {
+        self.storage.shutdown().await
+    }
+}
@@ -0,0 +1,321 @@
+//! Cache administration commands, which operate directly on the configured storage tiers
+//! rather than going through a running server.
+
+use bytes::Bytes;
+use embed::timestamp::Timestamp;
+use hashbrown::HashSet;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::cache::storage::{Cache, CacheNameInner, CacheRecord, CacheStorage, ScanEntry};
+use crate::cache::tier::Policy;
+use crate::config::Config;
+use crate::error::Error;
+
+pub const USAGE: &str = "\
+Usage:
+    embed-server                                Run the server
+    embed-server cache stats [OPTIONS]          Print the number and size of entries in each tier
+    embed-server cache dump [OPTIONS]           Write entries to stdout as NDJSON
+    embed-server cache import [OPTIONS]         Read NDJSON entries from stdin into each tier
+    embed-server cache purge [OPTIONS]          Delete entries by domain and/or expiration
+
+Options:
+    --tier <name>       Only operate on the given tier, e.g. `sqlite`. Read-only tiers
+                        are skipped by import and purge unless named here.
+    --domain <domain>   Only include entries for this domain and its subdomains
+    --expired           Only include expired entries (purge only)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Stats,
+    Dump,
+    Import,
+    Purge,
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub command: Command,
+    pub tier: Option<String>,
+    pub domain: Option<String>,
+    pub expired: bool,
+}
+
+impl Args {
+    /// Parse the arguments after the binary name
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        match args.next().as_deref() {
+            Some("cache") => {}
+            Some(other) => return Err(format!("Unknown command: {other}")),
+            None => return Err("Missing command".to_owned()),
+        }
+
+        let command = match args.next().as_deref() {
+            Some("stats") => Command::Stats,
+            Some("dump") => Command::Dump,
+            Some("import") => Command::Import,
+            Some("purge") => Command::Purge,
+            Some(other) => return Err(format!("Unknown cache command: {other}")),
+            None => return Err("Missing cache command".to_owned()),
+        };
+
+        let mut parsed = Args {
+            command,
+            tier: None,
+            domain: None,
+            expired: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--tier" => parsed.tier = Some(args.next().ok_or("Missing value for --tier")?),
+                "--domain" => {
+                    let domain = args.next().ok_or("Missing value for --domain")?;
+
+                    parsed.domain = Some(domain.trim_matches('.').to_ascii_lowercase());
+                }
+                "--expired" => parsed.expired = true,
+                _ => return Err(format!("Unknown option: {arg}")),
+            }
+        }
+
+        match parsed.command {
+            Command::Purge if parsed.domain.is_none() && !parsed.expired => {
+                return Err("Purge requires --domain and/or --expired".to_owned());
+            }
+            Command::Stats | Command::Dump | Command::Import if parsed.expired => {
+                return Err("--expired is only valid for purge".to_owned());
+            }
+            Command::Import if parsed.domain.is_some() => {
+                return Err("--domain is not valid for import".to_owned());
+            }
+            _ => {}
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Check if the URL is on the given domain or one of its subdomains
+fn matches_domain(key: &[u8], domain: &str) -> bool {
+    let Some(url) = std::str::from_utf8(key).ok().and_then(|key| url::Url::parse(key).ok()) else {
+        return false;
+    };
+
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    match host.strip_suffix(domain) {
+        Some(prefix) => prefix.is_empty() || prefix.ends_with('.'),
+        None => false,
+    }
+}
+
+/// Scan the tier, calling `f` for each entry as it arrives
+async fn scan(tier: &Cache, mut f: impl FnMut(ScanEntry)) -> Result<(), Error> {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(256);
+
+    let (res, ()) = tokio::join!(tier.scan(tx), async {
+        while let Some(entry) = rx.recv().await {
+            f(entry);
+        }
+    });
+
+    res
+}
+
+/// Open each configured tier in order, or only the one named by `--tier`.
+///
+/// When opening them to `write` to, tiers with `mode = "read_only"` are skipped unless named explicitly.
+fn open_tiers(
+    config: &Config,
+    only: Option<&str>,
+    write: bool,
+) -> Result<Vec<(CacheNameInner, Cache)>, Error> {
+    let mut configs = config.parsed.cache.iter().collect::<Vec<_>>();
+
+    configs.sort_by_key(|c| c.0.order);
+
+    let mut tiers = Vec::new();
+
+    for (name, config) in configs {
+        if only.is_some_and(|only| !only.eq_ignore_ascii_case(name.inner.name())) {
+            continue;
+        }
+
+        if write && only.is_none() && !Policy::create(name.inner, config)?.write {
+            eprintln!("Skipping read-only {} tier", name.inner.name());
+            continue;
+        }
+
+        tiers.push((name.inner, name.inner.create(config)?));
+    }
+
+    Ok(tiers)
+}
+
+pub async fn run(config: &Config, args: Args) -> Result<(), Error> {
+    let write = matches!(args.command, Command::Import | Command::Purge);
+    let tiers = open_tiers(config, args.tier.as_deref(), write)?;
+
+    if tiers.is_empty() {
+        eprintln!("No matching cache tiers configured");
+    }
+
+    let res = match args.command {
+        Command::Stats => stats(&tiers, args.domain.as_deref()).await,
+        Command::Dump => dump(&tiers, args.domain.as_deref()).await,
+        Command::Import => import(&tiers).await,
+        Command::Purge => purge(&tiers, args.domain.as_deref(), args.expired).await,
+    };
+
+    // don't let a failed shutdown hide the result of the command itself
+    for (name, tier) in tiers {
+        if let Err(e) = tier.shutdown().await {
+            log::error!("Error shutting down {} cache tier: {e}", name.name());
+        }
+    }
+
+    res
+}
+
+async fn stats(tiers: &[(CacheNameInner, Cache)], domain: Option<&str>) -> Result<(), Error> {
+    let now = Timestamp::now_utc();
+
+    for (name, tier) in tiers {
+        let (mut entries, mut expired, mut size) = (0usize, 0usize, 0usize);
+
+        scan(tier, |entry| {
+            if domain.is_some_and(|domain| !matches_domain(&entry.key, domain)) {
+                return;
+            }
+
+            entries += 1;
+            size += entry.size;
+
+            if entry.embed.expires() < now {
+                expired += 1;
+            }
+        })
+        .await?;
+
+        println!(
+            "{}: {entries} entries ({expired} expired), {size} bytes",
+            name.name()
+        );
+    }
+
+    Ok(())
+}
+
+async fn dump(tiers: &[(CacheNameInner, Cache)], domain: Option<&str>) -> Result<(), Error> {
+    use std::io::Write;
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut failed = None;
+
+    // the same entry may be in multiple tiers, so only dump it from the first
+    let mut seen = HashSet::new();
+
+    for (_, tier) in tiers {
+        scan(tier, |entry| {
+            if failed.is_some() || domain.is_some_and(|domain| !matches_domain(&entry.key, domain)) {
+                return;
+            }
+
+            if !seen.insert(entry.key.clone()) {
+                return;
+            }
+
+            let record = CacheRecord {
+                url: String::from_utf8_lossy(&entry.key).into_owned(),
+                embed: entry.embed,
+            };
+
+            let res = match json_impl::to_string(&record) {
+                Ok(json) => writeln!(out, "{json}").map_err(Error::from),
+                Err(e) => Err(e.into()),
+            };
+
+            failed = res.err();
+        })
+        .await?;
+
+        if let Some(e) = failed.take() {
+            return Err(e);
+        }
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+async fn import(tiers: &[(CacheNameInner, Cache)]) -> Result<(), Error> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let (mut imported, mut expired, mut invalid) = (0usize, 0usize, 0usize);
+
+    let now = Timestamp::now_utc();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: CacheRecord = match json_impl::from_str(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                log::warn!("Skipping invalid line: {e}");
+                invalid += 1;
+                continue;
+            }
+        };
+
+        if record.embed.expires() < now {
+            expired += 1;
+            continue;
+        }
+
+        let key = Bytes::from(record.url);
+
+        for (_, tier) in tiers {
+            tier.put(now, key.clone(), record.embed.clone()).await?;
+        }
+
+        imported += 1;
+    }
+
+    eprintln!("Imported {imported} entries, skipped {expired} expired and {invalid} invalid");
+
+    Ok(())
+}
+
+async fn purge(tiers: &[(CacheNameInner, Cache)], domain: Option<&str>, expired: bool) -> Result<(), Error> {
+    let now = Timestamp::now_utc();
+
+    for (name, tier) in tiers {
+        let mut keys = Vec::new();
+
+        scan(tier, |entry| {
+            if domain.is_some_and(|domain| !matches_domain(&entry.key, domain)) {
+                return;
+            }
+
+            if expired && entry.embed.expires() >= now {
+                return;
+            }
+
+            keys.push(entry.key);
+        })
+        .await?;
+
+        for key in &keys {
+            tier.del(key.clone()).await?;
+        }
+
+        println!("{}: purged {} entries", name.name(), keys.len());
+    }
+
+    Ok(())
+}
@@ -7,6 +7,7 @@ use reqwest::header::HeaderName;
 use self::header::DeHeaderValue;
 
 pub mod header;
+pub mod oembed;
 pub mod pattern;
 
 #[derive(Debug, thiserror::Error)]
@@ -31,6 +32,13 @@ pub enum ConfigError {
 
     #[error("Invalid cache field: cache.{0}")]
     InvalidCacheField(&'static str),
+
+    /// For fields shared by every tier, with the tier name first
+    #[error("Invalid cache field: cache.{0}.{1}")]
+    InvalidTierField(&'static str, &'static str),
+
+    #[error("Invalid oEmbed providers list: {0}")]
+    InvalidOEmbedProviders(String),
 }
 
 #[derive(Debug, Clone, Copy, serde::Deserialize)]
@@ -47,6 +55,9 @@ pub struct Limits {
 
     /// Maximum number of images to include in an embed
     pub max_images: usize,
+
+    /// Maximum number of feed entries to include as fields
+    pub max_feed_entries: usize,
 }
 
 impl Default for Limits {
@@ -56,6 +67,7 @@ impl Default for Limits {
             max_xml_size: 1024 * 1024,  // 1 MiB
             max_media_size: 1024 * 1024,
             max_images: 4,
+            max_feed_entries: 5,
         }
     }
 }
@@ -65,8 +77,17 @@ pub struct ParsedConfig {
     #[serde(default = "defaults::default_redirects")]
     pub max_redirects: u32,
 
-    #[serde(default = "defaults::default_cache_size")]
-    pub cache_size: usize,
+    /// Approximate maximum memory used by the in-memory cache, in bytes
+    #[serde(default = "defaults::default_memory_budget")]
+    pub memory_budget: usize,
+
+    /// Deprecated entry limit of the in-memory cache, replaced by `memory_budget`. Only read to warn about it.
+    #[serde(default)]
+    pub cache_size: Option<usize>,
+
+    /// Maximum time to keep an embed in memory, in seconds, regardless of its expiration
+    #[serde(default)]
+    pub memory_max_ttl: Option<u64>,
 
     /// Request timeout, in milliseconds
     #[serde(default = "defaults::default_timeout")]
@@ -90,6 +111,24 @@ pub struct ParsedConfig {
     #[serde(default)]
     pub skip_oembed: Vec<String>,
 
+    /// Path to a JSON list of oEmbed providers, in the format of <https://oembed.com/providers.json>.
+    /// Uses the bundled list if not set, or none at all if empty.
+    #[serde(default)]
+    pub oembed_providers: Option<String>,
+
+    /// `maxwidth` sent to oEmbed providers, and the limit for the per-request `mw` parameter
+    #[serde(default)]
+    pub oembed_max_width: Option<u32>,
+
+    /// `maxheight` sent to oEmbed providers, and the limit for the per-request `mh` parameter
+    #[serde(default)]
+    pub oembed_max_height: Option<u32>,
+
+    /// How long to remember the icon found at each origin when pages don't declare one, in seconds.
+    /// Set to 0 to never probe for them.
+    #[serde(default = "defaults::default_favicon_ttl")]
+    pub favicon_ttl: u64,
+
     #[serde(default)]
     pub sites: HashMap<String, Arc<Site>>,
 
@@ -113,7 +152,8 @@ mod defaults {
     pub const fn default_resolve_media() -> bool { true }
     pub const fn default_signed() -> bool { true }
     pub const fn default_markdown() -> bool { true }
-    pub const fn default_cache_size() -> usize { 1024 }
+    pub const fn default_memory_budget() -> usize { 64 * 1024 * 1024 }
+    pub const fn default_favicon_ttl() -> u64 { 60 * 60 * 24 * 7 }
 }
 
 #[derive(Default, Debug, Clone, serde::Deserialize)]
@@ -239,13 +279,22 @@ pub struct Config {
 
     pub allow_html: SitePatterns,
     pub skip_oembed: SitePatterns,
+    pub oembed_providers: oembed::OEmbedProviders,
 }
 
 impl ParsedConfig {
     pub fn build(self) -> Result<Config, ConfigError> {
+        if let Some(cache_size) = self.cache_size {
+            log::warn!(
+                "`cache_size = {cache_size}` is deprecated and ignored, the in-memory cache is now bounded by `memory_budget` ({} bytes)",
+                self.memory_budget
+            );
+        }
+
         Ok(Config {
             allow_html: SitePatterns::new(&self, self.allow_html.iter(), "allow_html")?,
             skip_oembed: SitePatterns::new(&self, self.skip_oembed.iter(), "skip_oembed")?,
+            oembed_providers: oembed::OEmbedProviders::load(self.oembed_providers.as_deref())?,
             parsed: self,
         })
     }
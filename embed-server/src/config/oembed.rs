@@ -0,0 +1,156 @@
+//! Registry of known oEmbed providers, in the format of <https://oembed.com/providers.json>
+//!
+//! URLs matching a provider's schemes can be sent directly to its endpoint,
+//! without downloading the page to discover it first.
+
+use std::borrow::Cow;
+
+use regex::RegexSet;
+
+use super::ConfigError;
+use crate::parser::oembed::{OEmbedFormat, OEmbedLink};
+
+/// Used when `oembed_providers` isn't configured
+static BUNDLED: &str = include_str!("oembed_providers.json");
+
+#[derive(Debug, serde::Deserialize)]
+struct RawProvider {
+    provider_name: String,
+
+    #[serde(default)]
+    endpoints: Vec<RawEndpoint>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RawEndpoint {
+    #[serde(default)]
+    schemes: Vec<String>,
+
+    url: String,
+}
+
+#[derive(Debug)]
+pub struct OEmbedProviders {
+    /// One pattern per scheme
+    schemes: RegexSet,
+
+    /// Index into `endpoints` for each scheme
+    scheme_endpoints: Vec<usize>,
+
+    /// `(provider name, endpoint url)`
+    endpoints: Vec<(String, String)>,
+}
+
+impl Default for OEmbedProviders {
+    fn default() -> Self {
+        OEmbedProviders {
+            schemes: RegexSet::empty(),
+            scheme_endpoints: Vec::new(),
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+/// Convert a scheme like `https://*.youtube.com/watch*` into an anchored regex,
+/// accepting either http or https regardless of which is listed
+fn scheme_to_regex(scheme: &str) -> String {
+    let Some((proto, rest)) = scheme.split_once("://") else {
+        return format!("^{}$", regex::escape(scheme).replace(r"\*", ".*"));
+    };
+
+    let proto = match proto {
+        "http" | "https" => "https?",
+        _ => proto,
+    };
+
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+    // wildcards in the host must not cross into the path
+    format!(
+        "^{proto}://{}{}$",
+        regex::escape(host).replace(r"\*", "[^/]*"),
+        regex::escape(path).replace(r"\*", ".*")
+    )
+}
+
+impl OEmbedProviders {
+    /// Load the providers list from the given path, or the bundled list
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        let raw: Vec<RawProvider> = match path {
+            None => serde_json::from_str(BUNDLED),
+            Some("") => return Ok(OEmbedProviders::default()),
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(file) => serde_json::from_str(&file),
+                Err(e) => return Err(ConfigError::InvalidOEmbedProviders(e.to_string())),
+            },
+        }
+        .map_err(|e| ConfigError::InvalidOEmbedProviders(e.to_string()))?;
+
+        let mut patterns = Vec::new();
+        let mut providers = OEmbedProviders::default();
+
+        for provider in raw {
+            for endpoint in provider.endpoints {
+                if endpoint.schemes.is_empty() {
+                    continue; // discovery-only
+                }
+
+                for scheme in &endpoint.schemes {
+                    patterns.push(scheme_to_regex(scheme));
+                    providers.scheme_endpoints.push(providers.endpoints.len());
+                }
+
+                providers.endpoints.push((provider.provider_name.clone(), endpoint.url));
+            }
+        }
+
+        providers.schemes =
+            RegexSet::new(patterns).map_err(|_| ConfigError::InvalidRegex("oembed_providers"))?;
+
+        Ok(providers)
+    }
+
+    /// Find the oEmbed endpoint for the given URL, if it matches any known provider
+    pub fn find(&self, url: &str) -> Option<OEmbedLink<'static>> {
+        let idx = self.schemes.matches(url).into_iter().next()?;
+        let (name, endpoint) = &self.endpoints[self.scheme_endpoints[idx]];
+
+        let endpoint = endpoint.replace("{format}", "json");
+
+        let link = url::Url::parse_with_params(&endpoint, &[("url", url), ("format", "json")]).ok()?;
+
+        log::trace!("Using {name} oEmbed provider for {url}");
+
+        Some(OEmbedLink {
+            url: Cow::Owned(link.into()),
+            title: None,
+            format: OEmbedFormat::JSON,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_providers() {
+        let providers = OEmbedProviders::load(None).unwrap();
+
+        let link = providers.find("http://youtu.be/dQw4w9WgXcQ").unwrap();
+
+        assert_eq!(
+            link.url,
+            "https://www.youtube.com/oembed?url=http%3A%2F%2Fyoutu.be%2FdQw4w9WgXcQ&format=json"
+        );
+
+        assert!(providers
+            .find("https://vimeo.com/76979871")
+            .unwrap()
+            .url
+            .starts_with("https://vimeo.com/api/oembed.json?"));
+        assert!(providers.find("https://example.com/watch?v=1").is_none());
+        assert!(providers.find("https://youtube.com.example.com/watch?v=1").is_none());
+        assert!(providers.find("https://example.com/.youtube.com/watch?v=1").is_none());
+    }
+}
@@ -28,6 +28,9 @@ pub enum Error {
     #[error(transparent)]
     UrlError(#[from] url::ParseError),
 
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+
     #[error("Cache Error: {0}")]
     CacheError(Arc<CacheError>),
 
@@ -70,6 +73,7 @@ impl Error {
                 None => StatusCode::INTERNAL_SERVER_ERROR,
             },
             Error::JsonError(_) | Error::XMLError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::CacheError(err) => err.error.status_code(),
 
             #[cfg(feature = "cache_redis")]
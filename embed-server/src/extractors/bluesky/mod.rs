@@ -25,6 +25,10 @@ use models::*;
 
 #[async_trait::async_trait]
 impl Extractor for BlueskyExtractor {
+    fn name(&self) -> &'static str {
+        "bluesky"
+    }
+
     #[allow(clippy::match_like_matches_macro)]
     fn matches(&self, url: &Url) -> bool {
         matches!(url.domain(), Some("bsky.app"))
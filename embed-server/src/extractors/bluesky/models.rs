@@ -226,14 +226,9 @@ where
 {
     use core::fmt::Write;
 
-    match (ts, md) {
-        (Some(ts), false) => write!(w, "{ts} - ")?,
-        (Some(ts), true) => write!(
-            w,
-            "<t:{}> - ",
-            ts.duration_since(Timestamp::UNIX_EPOCH).whole_seconds()
-        )?,
-        _ => {}
+    if let Some(ts) = ts {
+        crate::util::write_timestamp(&mut *w, ts, md)?;
+        w.write_str(" - ")?;
     }
 
     let symbols = [
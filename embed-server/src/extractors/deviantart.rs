@@ -12,6 +12,10 @@ impl ExtractorFactory for DeviantArtExtractor {
 
 #[async_trait::async_trait]
 impl Extractor for DeviantArtExtractor {
+    fn name(&self) -> &'static str {
+        "deviantart"
+    }
+
     fn matches(&self, url: &Url) -> bool {
         match url.domain() {
             Some(d) if d.ends_with("deviantart.com") && url.path().contains("/art/") => true,
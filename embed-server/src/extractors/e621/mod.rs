@@ -32,6 +32,10 @@ pub struct E621Extractor {
 
 #[async_trait::async_trait]
 impl Extractor for E621Extractor {
+    fn name(&self) -> &'static str {
+        "e621"
+    }
+
     fn matches(&self, url: &Url) -> bool {
         // TODO: Support more than /posts/
         matches!(url.domain(), Some("e621.net" | "e926.net")) && url.path().starts_with("/posts/")
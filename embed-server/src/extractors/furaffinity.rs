@@ -49,6 +49,10 @@ pub struct FurAffinityExtractor {
 
 #[async_trait::async_trait]
 impl Extractor for FurAffinityExtractor {
+    fn name(&self) -> &'static str {
+        "furaffinity"
+    }
+
     fn matches(&self, url: &Url) -> bool {
         matches!(url.domain(), Some("furaffinity.net" | "www.furaffinity.net"))
             && url.path().starts_with("/view/")
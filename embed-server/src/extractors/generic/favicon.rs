@@ -0,0 +1,123 @@
+//! Last resort for sites that don't declare any icon in their HTML or manifest,
+//! by probing the well-known paths at their origin.
+//!
+//! Results, including finding nothing, are cached per origin for `favicon_ttl` seconds,
+//! as every page from the same site would give the same answer. Concurrent misses for
+//! the same origin wait for a single probe.
+
+use tokio::sync::watch;
+
+use super::*;
+
+type Icon = Option<Box<EmbedMedia>>;
+
+/// Well-known icon paths, in order of preference
+const PATHS: &[&str] = &["/favicon.ico", "/apple-touch-icon.png"];
+
+/// Number of origins to hold before purging expired entries
+const MAX_ORIGINS: usize = 16 * 1024;
+
+#[derive(Default)]
+pub struct FaviconCache {
+    origins: scc::HashMap<SmolStr, (Icon, Timestamp), ahash::RandomState>,
+
+    /// Probes in progress, which send the icon once found or not
+    pending: scc::HashMap<SmolStr, watch::Receiver<Option<Icon>>, ahash::RandomState>,
+}
+
+impl FaviconCache {
+    /// Find the icon for the origin of the given URL, probing for it if not already cached
+    pub async fn find(&self, state: &ServiceState, url: &Url) -> Icon {
+        let origin = url.origin();
+
+        if !origin.is_tuple() {
+            return None;
+        }
+
+        let origin = SmolStr::from(origin.ascii_serialization());
+        let now = Timestamp::now_utc();
+
+        let cached = self.origins.read_async(&origin, |_, (icon, expires)| {
+            (*expires > now).then(|| icon.clone())
+        });
+
+        if let Some(icon) = cached.await.flatten() {
+            return icon;
+        }
+
+        let tx = match self.pending.entry_async(origin.clone()).await {
+            // the request that was probing it was dropped, so take over
+            scc::hash_map::Entry::Occupied(mut occ) if occ.get().has_changed().is_err() => {
+                let (tx, rx) = watch::channel(None);
+                *occ.get_mut() = rx;
+                tx
+            }
+            scc::hash_map::Entry::Occupied(occ) => {
+                let mut rx = occ.get().clone();
+                drop(occ);
+
+                return match rx.wait_for(Option::is_some).await {
+                    Ok(icon) => icon.clone().flatten(),
+                    Err(_) => None,
+                };
+            }
+            scc::hash_map::Entry::Vacant(vac) => {
+                let (tx, rx) = watch::channel(None);
+                vac.insert_entry(rx);
+                tx
+            }
+        };
+
+        let icon = probe(state, &origin).await;
+
+        if self.origins.len() >= MAX_ORIGINS {
+            self.origins.retain_async(|_, (_, expires)| *expires > now).await;
+        }
+
+        let ttl = Duration::seconds(state.config.parsed.favicon_ttl as i64);
+
+        self.origins
+            .upsert_async(
+                origin.clone(),
+                (icon.clone(), now.checked_add(ttl).unwrap_or(now)),
+            )
+            .await;
+
+        // only after caching it, so later misses don't start another probe
+        self.pending.remove_async(&origin).await;
+
+        let _ = tx.send(Some(icon.clone()));
+
+        icon
+    }
+}
+
+async fn probe(state: &ServiceState, origin: &str) -> Icon {
+    for path in PATHS {
+        let resp = match state.client.head(format!("{origin}{path}")).send().await {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(_) => continue,
+            Err(e) => {
+                log::trace!("Error probing {origin}{path}: {e}");
+                continue;
+            }
+        };
+
+        let mime = resp.headers().get("content-type").and_then(|h| h.to_str().ok());
+
+        // sites that route everything to their index page will respond with HTML for any path
+        let Some(mime) =
+            mime.and_then(|m| m.split(';').next()).map(str::trim).filter(|m| m.starts_with("image/"))
+        else {
+            continue;
+        };
+
+        let mut media = Box::<EmbedMedia>::default().with_url(resp.url().as_str());
+
+        media.mime = Some(mime.into());
+
+        return Some(media);
+    }
+
+    None
+}
@@ -0,0 +1,198 @@
+//! Embeds for downloads that can't be previewed, such as archives, documents and binaries.
+//!
+//! Everything is taken from the response headers, and the body is never read. If the size isn't known
+//! from `Content-Length`, a ranged request for a single byte gives it in `Content-Range` instead.
+
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, LAST_MODIFIED, RANGE};
+
+use super::*;
+
+#[derive(Debug, Default)]
+pub struct FileInfo {
+    pub name: Option<String>,
+    pub size: Option<u64>,
+    pub mime: String,
+    pub modified: Option<Timestamp>,
+}
+
+impl FileInfo {
+    pub fn from_headers(url: &Url, mime: &str, headers: &HeaderMap) -> FileInfo {
+        let header = |name: HeaderName| headers.get(name).and_then(|h| h.to_str().ok());
+
+        let name = header(CONTENT_DISPOSITION)
+            .and_then(disposition_filename)
+            .or_else(|| url.path_segments()?.last().filter(|s| !s.is_empty()).map(percent_decode))
+            .map(|name| name.trim().to_owned())
+            .filter(|name| !name.is_empty());
+
+        // servers often don't know either, so try to guess from the name
+        let mime = match mime {
+            "application/octet-stream" | "binary/octet-stream" => {
+                name.as_deref().and_then(|name| mime_guess::from_path(name).first_raw()).unwrap_or(mime)
+            }
+            _ => mime,
+        };
+
+        FileInfo {
+            name,
+            size: header(CONTENT_LENGTH).and_then(|len| len.parse().ok()),
+            mime: mime.to_owned(),
+            modified: header(LAST_MODIFIED).and_then(parse_http_date),
+        }
+    }
+}
+
+/// Request a single byte of the file to find its total size
+pub async fn fetch_size(state: &ServiceState, site: &Option<Arc<Site>>, url: &Url) -> Option<u64> {
+    let mut req = state.client.get(url.as_str()).header(RANGE, "bytes=0-0");
+
+    if let Some(ref site) = site {
+        req = site.add_headers(&state.config, req);
+    }
+
+    let resp = req.send().await.ok()?;
+
+    match resp.status() {
+        StatusCode::PARTIAL_CONTENT => range_size(resp.headers()),
+        // range ignored, but might have the length this time
+        status if status.is_success() => resp.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Total size of the file from the `Content-Range` header of a partial response, e.g. `bytes 0-0/1234`
+pub fn range_size(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_RANGE)?.to_str().ok()?.rsplit('/').next()?.trim().parse().ok()
+}
+
+pub fn file_into_embed(embed: &mut EmbedV1, info: FileInfo, markdown: bool) {
+    let mut push_field = |name: &str, value: &str| {
+        embed.fields.push(EmbedField {
+            name: From::from(name),
+            value: From::from(value),
+            ..EmbedField::default()
+        })
+    };
+
+    if let Some(ref name) = info.name {
+        push_field("Name", name);
+    }
+
+    if let Some(size) = info.size {
+        push_field("Size", &format_size(size));
+    }
+
+    push_field("Type", &info.mime);
+
+    if embed.title.is_none() {
+        embed.title = info.name.map(From::from);
+    }
+
+    if let Some(modified) = info.modified {
+        let mut footer = EmbedFooter::default();
+
+        if footer.text.write_str("Last modified ").is_ok()
+            && crate::util::write_timestamp(&mut footer.text, modified, markdown).is_ok()
+        {
+            embed.footer = Some(footer);
+        }
+    }
+}
+
+/// Get the filename from a `Content-Disposition` header, preferring the extended `filename*` parameter
+fn disposition_filename(disposition: &str) -> Option<String> {
+    let mut filename = None;
+
+    for param in disposition.split(';').skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+
+        match key.trim() {
+            // filename*=UTF-8''na%C3%AFve.txt
+            key if key.eq_ignore_ascii_case("filename*") => {
+                if let Some((_, encoded)) = value.trim().split_once("''") {
+                    return Some(percent_decode(encoded));
+                }
+            }
+            key if key.eq_ignore_ascii_case("filename") => {
+                filename = Some(value.trim().trim_matches('"').to_owned());
+            }
+            _ => {}
+        }
+    }
+
+    filename
+}
+
+fn percent_decode(s: &str) -> String {
+    percent_encoding::percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+fn parse_http_date(date: &str) -> Option<Timestamp> {
+    let since_epoch = httpdate::parse_http_date(date).ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+
+    Timestamp::UNIX_EPOCH.checked_add(Duration::seconds(since_epoch.as_secs() as i64))
+}
+
+/// Format a number of bytes with binary units, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+
+    for u in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+
+        size /= 1024.0;
+        unit = u;
+    }
+
+    format!("{size:.1} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_info() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_static(
+                r#"attachment; filename="fallback.zip"; filename*=UTF-8''na%C3%AFve%20file.zip"#,
+            ),
+        );
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1572864"));
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 03 Jan 2024 12:00:00 GMT"),
+        );
+
+        let url = Url::parse("https://example.com/download?id=1").unwrap();
+        let info = FileInfo::from_headers(&url, "application/octet-stream", &headers);
+
+        assert_eq!(info.name.as_deref(), Some("naïve file.zip"));
+        assert_eq!(info.size, Some(1572864));
+        assert_eq!(info.mime, "application/zip");
+        assert_eq!(info.modified, Timestamp::parse("2024-01-03T12:00:00Z"));
+
+        // name from the URL instead
+        let url = Url::parse("https://example.com/files/setup%20v2.exe").unwrap();
+        let info = FileInfo::from_headers(&url, "application/x-msdownload", &HeaderMap::new());
+
+        assert_eq!(info.name.as_deref(), Some("setup v2.exe"));
+        assert_eq!(info.size, None);
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1572864), "1.5 MiB");
+    }
+}
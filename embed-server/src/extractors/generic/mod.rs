@@ -2,6 +2,8 @@ use hashbrown::HashMap;
 
 use super::prelude::*;
 
+use crate::parser::readability;
+
 #[derive(Debug)]
 pub struct GenericExtractor;
 
@@ -45,6 +47,116 @@ pub async fn extract_raw(
 
     let site = url.domain().and_then(|domain| state.config.find_site(domain));
 
+    let mut embed = EmbedV1::default();
+    let mut oembed: Option<OEmbed> = None;
+
+    // seconds until embed expires
+    let mut max_age = None;
+
+    embed.url = Some(url.as_str().into());
+
+    // known providers can be queried directly, without downloading the page first
+    if let Some(link) = state.config.oembed_providers.find(url.as_str()) {
+        match fetch_oembed(&state, &link, url.domain(), &params).await {
+            Ok(o) => oembed = o,
+            Err(e) => log::debug!("Failed to fetch oEmbed from known provider: {e}"),
+        }
+    }
+
+    // otherwise scrape the page, which may also link to an oEmbed endpoint
+    if oembed.is_none() {
+        max_age = scrape_page(&state, &url, &site, &params, &mut embed, &mut oembed).await?;
+    }
+
+    if let Some(oembed) = oembed {
+        let extra = crate::parser::embed::parse_oembed_to_embed(&mut embed, oembed);
+
+        max_age = extra.max_age;
+    }
+
+    crate::parser::quirks::resolve_relative(&url, &mut embed);
+
+    if state.config.parsed.resolve_media {
+        resolve_media::resolve_images(&state, &site, &mut embed).await?;
+    }
+
+    if let Some(domain) = url.domain() {
+        apply_html_policy(&state.config, domain, &mut embed);
+
+        if let Some(site) = site {
+            embed.color = site.color.or(embed.color);
+        }
+    }
+
+    Ok(RawGenericExtraction {
+        state,
+        embed,
+        max_age,
+    })
+}
+
+/// Remove HTML objects and players, unless both the page and the object itself are from allowed domains
+fn apply_html_policy(config: &Config, domain: &str, embed: &mut EmbedV1) {
+    let page_allowed = config.allow_html(domain).is_match();
+
+    let allowed = |media: &EmbedMedia| {
+        page_allowed
+            && Url::parse(&media.url)
+                .ok()
+                .and_then(|src| src.domain().map(|domain| config.allow_html(domain).is_match()))
+                .unwrap_or(false)
+    };
+
+    if !embed.obj.as_deref().is_some_and(allowed) {
+        embed.obj = None;
+    }
+
+    if let Some(ref vid) = embed.video {
+        if matches!(vid.mime, Some(ref mime) if mime.starts_with("text/html")) && !allowed(vid) {
+            embed.video = None;
+        }
+    }
+
+    // may have been an HTML embed before
+    crate::parser::embed::determine_embed_type(embed);
+}
+
+/// Footer with when the page was published, and when it was updated if that was much later
+fn article_footer(
+    published: Option<Timestamp>,
+    modified: Option<Timestamp>,
+    markdown: bool,
+) -> Option<EmbedFooter> {
+    let mut footer = EmbedFooter::default();
+
+    match (published, modified) {
+        (Some(published), Some(modified)) if modified.duration_since(published).whole_hours() >= 1 => {
+            crate::util::write_timestamp(&mut footer.text, published, markdown).ok()?;
+            footer.text.write_str(" (updated ").ok()?;
+            crate::util::write_timestamp(&mut footer.text, modified, markdown).ok()?;
+            footer.text.write_str(")").ok()?;
+        }
+        (Some(ts), _) | (None, Some(ts)) => {
+            crate::util::write_timestamp(&mut footer.text, ts, markdown).ok()?
+        }
+        (None, None) => return None,
+    }
+
+    Some(footer)
+}
+
+/// Download the page and build the embed from its contents, returning the max age if found
+async fn scrape_page(
+    state: &ServiceState,
+    url: &url::Url,
+    site: &Option<Arc<Site>>,
+    params: &Params,
+    embed: &mut EmbedV1,
+    oembed: &mut Option<OEmbed>,
+) -> Result<Option<u64>, Error> {
+    // seconds until embed expires
+    let mut max_age = None;
+
     let mut resp = retry_request(2, || {
         let mut req = state.client.get(url.as_str());
 
@@ -67,12 +179,6 @@ pub async fn extract_raw(
         return Err(Error::Failure(resp.status()));
     }
 
-    let mut embed = EmbedV1::default();
-    let mut oembed: Option<OEmbed> = None;
-
-    // seconds until embed expires
-    let mut max_age = None;
-
     if let Some(rating) = resp.headers().get(HeaderName::from_static("rating")) {
         if crate::parser::patterns::contains_adult_rating(rating.as_bytes()) {
             embed.flags |= EmbedFlags::ADULT;
@@ -85,11 +191,9 @@ pub async fn extract_raw(
         .and_then(|h| h.to_str().ok())
         .map(crate::parser::oembed::parse_link_header);
 
-    embed.url = Some(url.as_str().into());
-
     if let Some(link) = links.as_ref().and_then(|l| l.first()) {
-        if let Ok(o) = fetch_oembed(&state, link, url.domain()).await {
-            oembed = o;
+        if let Ok(o) = fetch_oembed(state, link, url.domain(), params).await {
+            *oembed = o;
         }
     }
 
@@ -104,44 +208,100 @@ pub async fn extract_raw(
             let max = state.config.parsed.limits.max_html_size;
             let mut html = Vec::with_capacity(max.min(512));
 
-            let body = read_body(&mut resp, &mut html, max).await?;
+            // selectors can match anything in the body, otherwise only the head and any structured data is needed
+            let head_only = !matches!(site, Some(ref site) if !site.fields.is_empty());
 
-            //std::fs::write("test.html", body).unwrap();
+            let mut end = read_html(&mut resp, &mut html, max, head_only).await?;
 
-            if let Some(headers) = crate::parser::html::parse_meta(body) {
-                let extra = crate::parser::embed::parse_meta_to_embed(&mut embed, &headers);
+            //std::fs::write("test.html", &html).unwrap();
 
-                match extra.link {
-                    Some(link) if oembed.is_none() => {
-                        if let Ok(o) = fetch_oembed(&state, &link, url.domain()).await {
-                            oembed = o;
-                        }
+            let max_images = state.config.parsed.limits.max_images;
+
+            // to start over if the rest of the page is needed
+            let initial = (end == stats::PageEnd::HeadOnly).then(|| embed.clone());
+
+            let mut extra = {
+                let body = decode_html(&resp, &html);
+
+                match site {
+                    // selectors need a full DOM anyway, so parse it once and take the headers from there too
+                    Some(ref site) if !site.fields.is_empty() => {
+                        let doc = scraper::Html::parse_document(&body);
+
+                        let headers = crate::parser::html::parse_dom(&doc);
+                        let extra = crate::parser::embed::parse_meta_to_embed(embed, &headers, max_images);
+
+                        scrape_fields::scrape_fields(&doc, embed, &site.fields);
+
+                        // the DOM can't be held across any awaits
+                        extra.into_owned()
                     }
-                    _ => {}
-                }
+                    _ => {
+                        let (headers, _) = crate::parser::html::parse_meta(&body, true);
 
-                match extra.manifest {
-                    Some(manifest_url) if web_manifest::needs_manifest(&embed) => {
-                        if let Err(e) =
-                            web_manifest::try_fetch_manifest(&state, &url, &manifest_url, &params, &mut embed)
-                                .await
-                        {
-                            log::warn!("Failed to fetch manifest: {e}");
-                        }
+                        crate::parser::embed::parse_meta_to_embed(embed, &headers, max_images).into_owned()
                     }
-                    _ => {}
+                }
+            };
+
+            // the metadata wasn't enough, so fall back to the page content, reading the rest of it if needed
+            if readability::is_sparse(embed) {
+                if initial.is_some() {
+                    end = read_html(&mut resp, &mut html, max, false).await?;
                 }
 
-                max_age = extra.max_age;
+                let body = decode_html(&resp, &html);
+
+                // the body may have structured data or metadata of its own, which beats guessing from the content
+                if let Some(initial) = initial {
+                    *embed = initial;
+
+                    let (headers, _) = crate::parser::html::parse_meta(&body, false);
+
+                    extra =
+                        crate::parser::embed::parse_meta_to_embed(embed, &headers, max_images).into_owned();
+                }
+
+                if readability::is_sparse(embed) {
+                    readability::apply_fallback(embed, &body);
+                }
+            }
+
+            state.html_stats.record(end, html.len(), resp.content_length());
+
+            drop(resp); // close the connection rather than waiting on the rest of the page
+
+            match extra.link {
+                Some(link) if oembed.is_none() => {
+                    if let Ok(o) = fetch_oembed(state, &link, url.domain(), params).await {
+                        *oembed = o;
+                    }
+                }
+                _ => {}
             }
 
-            match site {
-                Some(ref site) if !site.fields.is_empty() => {
-                    scrape_fields::scrape_fields(body, &mut embed, &site.fields)
+            match extra.manifest {
+                Some(manifest_url) if web_manifest::needs_manifest(embed) => {
+                    if let Err(e) =
+                        web_manifest::try_fetch_manifest(state, url, &manifest_url, params, embed).await
+                    {
+                        log::warn!("Failed to fetch manifest: {e}");
+                    }
                 }
                 _ => {}
             }
 
+            // last resort for pages that declare no icon, as it takes more requests
+            if embed.provider.icon.is_none() && state.config.parsed.favicon_ttl > 0 {
+                embed.provider.icon = state.favicons.find(state, url).await;
+            }
+
+            max_age = extra.max_age;
+
+            if embed.footer.is_none() {
+                embed.footer = article_footer(extra.published, extra.modified, state.config.parsed.markdown);
+            }
+
             drop(html); // ensure it lives long enough
         } else if matches!(
             mime,
@@ -151,11 +311,19 @@ pub async fn extract_raw(
             let mut body = Vec::with_capacity(max.min(512));
 
             if let Ok(_) = read_bytes(&mut resp, &mut body, max).await {
-                // TODO: Maybe set the timestamp parser to use iso8601_timestamp
+                let body = crate::parser::charset::decode_xml(response_charset(&resp), &body, url.domain());
+
                 let parser = feed_rs::parser::Builder::new().base_uri(Some(url.as_str())).build();
 
+                let options = crate::parser::feed::FeedOptions {
+                    max_entries: state.config.parsed.limits.max_feed_entries,
+                    markdown: state.config.parsed.markdown,
+                };
+
                 if let Ok(feed) = parser.parse(&*body) {
-                    max_age = Some(crate::parser::feed::feed_into_embed(&mut embed, feed));
+                    max_age = Some(crate::parser::feed::feed_into_embed(
+                        embed, feed, url, &body, options,
+                    ));
                 }
             }
 
@@ -188,48 +356,38 @@ pub async fn extract_raw(
                     embed.ty = EmbedType::Audio;
                     embed.audio = Some(media);
                 }
-                _ => {}
-            }
-        }
-    }
+                _ => {
+                    let mut info = file::FileInfo::from_headers(url, mime, resp.headers());
 
-    if let Some(oembed) = oembed {
-        let extra = crate::parser::embed::parse_oembed_to_embed(&mut embed, oembed);
+                    drop(resp); // never download the file itself
 
-        max_age = extra.max_age;
-    }
+                    let pdf = match info.mime.as_str() {
+                        "application/pdf" => pdf::fetch_pdf(state, site, url, info.size).await,
+                        _ => None,
+                    };
 
-    crate::parser::quirks::resolve_relative(&url, &mut embed);
-
-    if state.config.parsed.resolve_media {
-        resolve_media::resolve_images(&state, &site, &mut embed).await?;
-    }
-
-    if let Some(domain) = url.domain() {
-        if !state.config.allow_html(domain).is_match() {
-            embed.obj = None;
+                    if info.size.is_none() {
+                        info.size = file::fetch_size(state, site, url).await;
+                    }
 
-            if let Some(ref vid) = embed.video {
-                if matches!(vid.mime, Some(ref mime) if mime.starts_with("text/html")) {
-                    embed.video = None;
+                    match pdf {
+                        Some(pdf) => pdf::pdf_into_embed(embed, info, pdf, state.config.parsed.markdown),
+                        None => file::file_into_embed(embed, info, state.config.parsed.markdown),
+                    }
                 }
             }
         }
-
-        if let Some(site) = site {
-            embed.color = site.color.or(embed.color);
-        }
     }
 
-    Ok(RawGenericExtraction {
-        state,
-        embed,
-        max_age,
-    })
+    Ok(max_age)
 }
 
 #[async_trait::async_trait]
 impl Extractor for GenericExtractor {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
     fn matches(&self, _: &url::Url) -> bool {
         true
     }
@@ -273,6 +431,7 @@ pub async fn fetch_oembed(
     state: &ServiceState,
     link: &OEmbedLink<'_>,
     domain: Option<&str>,
+    params: &Params,
 ) -> Result<Option<OEmbed>, Error> {
     if let Some(domain) = domain {
         if state.config.skip_oembed(domain).is_match() {
@@ -280,7 +439,29 @@ pub async fn fetch_oembed(
         }
     }
 
-    let body = state.client.get(&*link.url).send().await?.bytes().await?;
+    let Ok(mut oembed_url) = Url::parse(&link.url) else {
+        return Err(Error::InvalidUrl);
+    };
+
+    // per-request limits can only be smaller than the configured limits
+    let limit = |param: Option<u32>, max: Option<u32>| match (param, max) {
+        (Some(param), Some(max)) => Some(param.min(max)),
+        (param, max) => param.or(max),
+    };
+
+    let max_width = limit(params.max_width, state.config.parsed.oembed_max_width);
+    let max_height = limit(params.max_height, state.config.parsed.oembed_max_height);
+
+    for (key, value) in [("maxwidth", max_width), ("maxheight", max_height)] {
+        if let Some(value) = value {
+            // don't override anything the provider already specified in the link
+            if !oembed_url.query_pairs().any(|(k, _)| k == key) {
+                oembed_url.query_pairs_mut().append_pair(key, &value.to_string());
+            }
+        }
+    }
+
+    let body = state.client.get(oembed_url.as_str()).send().await?.bytes().await?;
 
     Ok(Some(match link.format {
         OEmbedFormat::JSON => json_impl::from_slice(&body)?,
@@ -288,30 +469,71 @@ pub async fn fetch_oembed(
     }))
 }
 
-pub async fn read_body<'a>(
+/// Download an HTML page up to `max` bytes, optionally stopping once the `<head>` has been read.
+///
+/// Can be called again with `head_only = false` to continue reading the rest of the page.
+pub async fn read_html(
     resp: &mut reqwest::Response,
-    html: &'a mut Vec<u8>,
+    html: &mut Vec<u8>,
     max: usize,
-) -> Result<&'a str, Error> {
+    mut head_only: bool,
+) -> Result<stats::PageEnd, Error> {
+    // Limits of HTML downloaded, assume it's a broken page or DoS attack and don't bother with more
+    if html.len() > max {
+        return Ok(stats::PageEnd::Truncated);
+    }
+
     while let Some(chunk) = resp.chunk().await? {
+        // the end tag could be split between chunks
+        let start = html.len().saturating_sub(6);
+
         html.extend(&chunk);
 
-        if memchr::memmem::rfind(html, b"</body").is_some() {
+        if head_only && find_head_end(&html[start..]) {
+            // pages using microdata or JSON-LD often have more of it in the body
+            if !has_structured_data(html) {
+                return Ok(stats::PageEnd::HeadOnly);
+            }
+
+            head_only = false;
+        }
+
+        if memchr::memmem::find(&html[start..], b"</body").is_some() {
             break;
         }
 
-        // Limits of HTML downloaded, assume it's a broken page or DoS attack and don't bother with more
         if html.len() > max {
-            break;
+            return Ok(stats::PageEnd::Truncated);
         }
     }
 
-    if let Cow::Owned(new_html) = String::from_utf8_lossy(html) {
-        *html = new_html.into();
-    }
+    Ok(stats::PageEnd::Complete)
+}
+
+/// Decode the HTML downloaded by [`read_html`] into UTF-8
+fn decode_html<'a>(resp: &reqwest::Response, html: &'a [u8]) -> Cow<'a, str> {
+    crate::parser::charset::decode_html(response_charset(resp), html, resp.url().domain())
+}
+
+/// Checks for `</head` or `<body`, ignoring case
+fn find_head_end(html: &[u8]) -> bool {
+    memchr::memchr_iter(b'<', html).any(|lt| {
+        let tag = &html[lt..];
+
+        matches!(tag.get(1..6), Some(t) if t.eq_ignore_ascii_case(b"/head"))
+            || matches!(tag.get(1..5), Some(t) if t.eq_ignore_ascii_case(b"body"))
+    })
+}
+
+/// Checks for `itemscope` or `application/ld+json`
+fn has_structured_data(html: &[u8]) -> bool {
+    [&b"itemscope"[..], b"ld+json"].into_iter().any(|marker| memchr::memmem::find(html, marker).is_some())
+}
+
+fn response_charset(resp: &reqwest::Response) -> Option<&str> {
+    let content_type = resp.headers().get("content-type")?.to_str().ok()?;
 
-    // SAFETY: Just converted it to lossy utf8, it's fine
-    Ok(unsafe { std::str::from_utf8_unchecked(html) })
+    crate::parser::charset::content_type_charset(content_type)
 }
 
 pub async fn read_bytes<'a>(
@@ -349,6 +571,10 @@ where
     }
 }
 
+pub mod favicon;
+pub mod file;
+pub mod pdf;
 pub mod resolve_media;
 pub mod scrape_fields;
+pub mod stats;
 pub mod web_manifest;
@@ -0,0 +1,103 @@
+//! Article-style embeds for PDF documents, from their metadata and page count.
+//!
+//! Only the start of the document is requested, up to part of `max_media_size`. If the document is larger,
+//! the end of it is fetched with another range request, as that's where the trailer and any updated metadata are.
+
+use reqwest::header::RANGE;
+
+use crate::parser::pdf::PdfInfo;
+
+use super::*;
+
+/// Request what's needed of the document to parse its metadata
+pub async fn fetch_pdf(
+    state: &ServiceState,
+    site: &Option<Arc<Site>>,
+    url: &Url,
+    size: Option<u64>,
+) -> Option<PdfInfo> {
+    let max = state.config.parsed.limits.max_media_size;
+
+    // leave room for the end of the document
+    let tail = max / 4;
+    let head = max - tail;
+
+    let mut req = state.client.get(url.as_str()).header(RANGE, format!("bytes=0-{}", head.max(1) - 1));
+
+    if let Some(ref site) = site {
+        req = site.add_headers(&state.config, req);
+    }
+
+    let mut resp = req.send().await.ok()?;
+
+    let size = match resp.status() {
+        StatusCode::PARTIAL_CONTENT => size.or_else(|| file::range_size(resp.headers())),
+        // servers that ignore the range send the whole document, which is still only read up to `head`
+        status if status.is_success() => size,
+        _ => return None,
+    };
+
+    let mut data = Vec::with_capacity(max.min(512));
+
+    read_bytes(&mut resp, &mut data, head).await.ok()?;
+
+    drop(resp);
+
+    let complete = match size {
+        Some(size) => data.len() as u64 >= size,
+        None => data.len() <= head,
+    };
+
+    if !complete && tail > 0 {
+        let mut req = state.client.get(url.as_str()).header(RANGE, format!("bytes=-{tail}"));
+
+        if let Some(ref site) = site {
+            req = site.add_headers(&state.config, req);
+        }
+
+        match req.send().await {
+            // servers that ignore the range would send the whole document
+            Ok(mut resp) if resp.status() == StatusCode::PARTIAL_CONTENT => {
+                data.push(b'\n');
+
+                if let Err(e) = read_bytes(&mut resp, &mut data, max).await {
+                    log::debug!("Failed to read end of PDF: {e}");
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::debug!("Failed to request end of PDF: {e}"),
+        }
+    }
+
+    crate::parser::pdf::parse_pdf(&data)
+}
+
+pub fn pdf_into_embed(embed: &mut EmbedV1, file: file::FileInfo, pdf: PdfInfo, markdown: bool) {
+    embed.ty = EmbedType::Article;
+
+    embed.title = pdf.title.or(file.name).map(From::from);
+    embed.description = pdf.subject.map(|subject| crate::util::trim_text(&subject).into());
+
+    embed.author = pdf.author.map(|name| EmbedAuthor {
+        name: name.into(),
+        ..EmbedAuthor::default()
+    });
+
+    if let Some(pages) = pdf.pages {
+        embed.fields.push(EmbedField {
+            name: From::from("Pages"),
+            value: From::from(pages.to_string().as_str()),
+            ..EmbedField::default()
+        });
+    }
+
+    if let Some(size) = file.size {
+        embed.fields.push(EmbedField {
+            name: From::from("Size"),
+            value: From::from(file::format_size(size).as_str()),
+            ..EmbedField::default()
+        });
+    }
+
+    embed.footer = article_footer(pdf.created, file.modified, markdown);
+}
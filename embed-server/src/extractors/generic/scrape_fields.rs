@@ -1,12 +1,10 @@
 use super::*;
 
-pub fn scrape_fields(html: &str, embed: &mut EmbedV1, fields: &SiteFieldSelectors) {
-    let doc = scraper::Html::parse_document(html);
-
+pub fn scrape_fields(doc: &scraper::Html, embed: &mut EmbedV1, fields: &SiteFieldSelectors) {
     macro_rules! extract {
         ($field:ident) => {
             match fields.$field {
-                Some(ref selector) => selector.extract(&doc),
+                Some(ref selector) => selector.extract(doc),
                 None => None,
             }
         };
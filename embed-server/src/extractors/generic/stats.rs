@@ -0,0 +1,65 @@
+//! Counters for how much of each HTML page is downloaded, exposed at `GET /stats/html`
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+pub struct HtmlCounters {
+    pages: AtomicU64,
+    head_only: AtomicU64,
+    truncated: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_skipped: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct HtmlStats {
+    /// Number of HTML pages downloaded
+    pub pages: u64,
+    /// Pages where the download stopped at the end of the `<head>`
+    pub head_only: u64,
+    /// Pages cut off at `max_html_size`
+    pub truncated: u64,
+    /// Total bytes of HTML read
+    pub bytes_read: u64,
+    /// Bytes never downloaded from pages that stopped early, if their length was known
+    pub bytes_skipped: u64,
+}
+
+/// How a page download ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageEnd {
+    Complete,
+    HeadOnly,
+    Truncated,
+}
+
+impl HtmlCounters {
+    pub fn record(&self, end: PageEnd, read: usize, length: Option<u64>) {
+        self.pages.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(read as u64, Ordering::Relaxed);
+
+        match end {
+            PageEnd::Complete => {}
+            PageEnd::HeadOnly => {
+                self.head_only.fetch_add(1, Ordering::Relaxed);
+
+                if let Some(length) = length {
+                    self.bytes_skipped.fetch_add(length.saturating_sub(read as u64), Ordering::Relaxed);
+                }
+            }
+            PageEnd::Truncated => {
+                self.truncated.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> HtmlStats {
+        HtmlStats {
+            pages: self.pages.load(Ordering::Relaxed),
+            head_only: self.head_only.load(Ordering::Relaxed),
+            truncated: self.truncated.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_skipped: self.bytes_skipped.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -32,6 +32,10 @@ const BAD_PATHS: &[&str] = &[
 
 #[async_trait::async_trait]
 impl Extractor for ImgurExtractor {
+    fn name(&self) -> &'static str {
+        "imgur"
+    }
+
     fn matches(&self, url: &Url) -> bool {
         if !matches!(url.domain(), Some("imgur.com" | "i.imgur.com")) {
             return false;
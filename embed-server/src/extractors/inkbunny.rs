@@ -53,6 +53,10 @@ impl ExtractorFactory for InkbunnyExtractorFactory {
 
 #[async_trait::async_trait]
 impl Extractor for InkbunnyExtractor {
+    fn name(&self) -> &'static str {
+        "inkbunny"
+    }
+
     fn matches(&self, url: &Url) -> bool {
         matches!(url.domain(), Some("inkbunny.net")) && url.path().starts_with("/s/")
     }
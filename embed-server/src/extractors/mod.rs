@@ -17,6 +17,15 @@ pub trait ExtractorFactory {
 
 #[async_trait::async_trait]
 pub trait Extractor: Send + Sync + std::fmt::Debug {
+    /// Unique name of this extractor, used to tag cached embeds
+    fn name(&self) -> &'static str;
+
+    /// Version of this extractor's output. Increment this when changing how embeds are extracted,
+    /// so cached embeds produced by older versions are treated as stale.
+    fn version(&self) -> u32 {
+        1
+    }
+
     /// Test if this extractor should be used for this domain
     fn matches(&self, url: &Url) -> bool;
 
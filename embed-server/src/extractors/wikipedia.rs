@@ -40,6 +40,10 @@ impl ExtractorFactory for WikipediaExtractorFactory {
 
 #[async_trait::async_trait]
 impl Extractor for WikipediaExtractor {
+    fn name(&self) -> &'static str {
+        "wikipedia"
+    }
+
     fn matches(&self, url: &Url) -> bool {
         matches!(url.domain(), Some(domain) if domain.ends_with("wikipedia.org"))
             && url.path().starts_with("/wiki/")
@@ -27,6 +27,7 @@ extern crate sonic_rs as json_impl;
 extern crate tracing as log;
 
 pub mod cache;
+pub mod cli;
 pub mod config;
 pub mod error;
 pub mod extractors;
@@ -42,7 +43,7 @@ use bytes::Bytes;
 
 use ftl::body::Json;
 use ftl::extract::{query::Query, State};
-use ftl::http::StatusCode;
+use ftl::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
 
 use futures_util::FutureExt;
 use std::{borrow::Cow, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
@@ -54,7 +55,24 @@ use crate::error::CacheError;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    let mut args = std::env::args().skip(1).peekable();
+
+    // any arguments are administration commands rather than running the server
+    let command = args.peek().is_some().then(|| {
+        cli::Args::parse(args).unwrap_or_else(|e| {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        })
+    });
+
+    match command {
+        // keep stdout clean for dumps
+        Some(_) => tracing_subscriber::fmt()
+            .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+            .with_writer(std::io::stderr)
+            .init(),
+        None => tracing_subscriber::fmt::init(),
+    }
 
     if let Err(error) = dotenv::dotenv() {
         warn!(?error, "Couldn't read .env file. Continuing execution anyway");
@@ -69,6 +87,15 @@ async fn main() {
         parsed.build().expect("Unable to build config")
     };
 
+    if let Some(command) = command {
+        if let Err(e) = cli::run(&config, command).await {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
     let signing_key =
         config.parsed.signed.then(|| std::env::var("CAMO_SIGNING_KEY").expect("CAMO_SIGNING_KEY not found"));
 
@@ -78,6 +105,9 @@ async fn main() {
         extractor.setup(state.clone()).await.expect("Failed to setup extractor");
     }
 
+    #[cfg(feature = "cache_redis")]
+    cache::invalidation::listen(&state);
+
     let addr =
         SocketAddr::from_str(&std::env::var("EMBED_BIND_ADDRESS").expect("EMBED_BIND_ADDRESS not found"))
             .expect("Unable to parse bind address");
@@ -96,6 +126,10 @@ async fn main() {
         let mut router = Router::<Arc<ServiceState>, Response>::with_state(state.clone());
 
         router.post("/", root);
+        router.delete("/", purge);
+        router.get("/cache/memory", memory_stats);
+        router.put("/cache/memory", set_memory_budget);
+        router.get("/stats/html", html_stats);
         router.fallback(|| async { StatusCode::NOT_FOUND });
 
         router
@@ -121,6 +155,8 @@ async fn main() {
 
     info!("Shutting down...");
 
+    state.cache.stop_listening().await;
+
     let state = Arc::into_inner(state).expect("State unavailable");
 
     state.cache.shutdown().await;
@@ -132,6 +168,16 @@ async fn main() {
 pub struct Params {
     #[serde(rename = "l")]
     pub lang: Option<String>,
+
+    /// Maximum width of HTML embeds from oEmbed, limited by `oembed_max_width`
+    ///
+    /// NOTE: Embeds are cached by URL alone, so this only applies when the embed isn't already cached
+    #[serde(rename = "mw")]
+    pub max_width: Option<u32>,
+
+    /// Maximum height of HTML embeds from oEmbed, limited by `oembed_max_height`, see `max_width`
+    #[serde(rename = "mh")]
+    pub max_height: Option<u32>,
 }
 
 async fn root(
@@ -158,18 +204,82 @@ async fn root(
     }
 }
 
+/// Purge the URL in the body from every cache tier, on every instance
+async fn purge(State(state): State<Arc<ServiceState>>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    if let Err(code) = authorize(&state, &headers, &body) {
+        return code;
+    }
+
+    if url::Url::parse(core::str::from_utf8(&body).unwrap_or_default()).is_err() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    state.cache.invalidate(body).await;
+
+    StatusCode::NO_CONTENT
+}
+
+async fn memory_stats(State(state): State<Arc<ServiceState>>) -> Json<cache::memory::MemoryStats> {
+    Json(state.cache.memory_stats())
+}
+
+/// How much of each HTML page was downloaded
+async fn html_stats(State(state): State<Arc<ServiceState>>) -> Json<extractors::generic::stats::HtmlStats> {
+    Json(state.html_stats.stats())
+}
+
+/// Administration endpoints require the body to be signed with the signing key, with the signature
+/// given as the `Authorization` header. Without a signing key they are disabled entirely.
+fn authorize(state: &ServiceState, headers: &HeaderMap, body: &[u8]) -> Result<(), StatusCode> {
+    let signature = headers.get(AUTHORIZATION).and_then(|h| h.to_str().ok()).unwrap_or_default();
+
+    match state.verify(body, signature) {
+        true => Ok(()),
+        false => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Change the in-memory cache budget at runtime, with the number of bytes as the body
+async fn set_memory_budget(
+    State(state): State<Arc<ServiceState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<cache::memory::MemoryStats>, StatusCode> {
+    authorize(&state, &headers, &body)?;
+
+    let Some(budget) = core::str::from_utf8(&body).ok().and_then(|b| b.trim().parse().ok()) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    if budget < cache::memory::MIN_BUDGET {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    info!(budget, "Setting in-memory cache budget");
+
+    state.cache.set_memory_budget(budget).await;
+
+    Ok(Json(state.cache.memory_stats()))
+}
+
 async fn inner(
     state: Arc<ServiceState>,
     orig_url: Bytes,
     params: Params,
 ) -> Result<TArc<extractors::EmbedWithExpire>, Error> {
-    use cache::{CacheHit, CacheState};
+    use cache::{storage::ExtractorVersion, CacheHit, CacheState, SingleFlight};
 
     let url = url::Url::parse(core::str::from_utf8(&orig_url).map_err(|_| Error::InvalidUrl)?)?;
 
     info!(%url, "Request with params: {params:?}");
 
-    let miss = match state.cache.get(&orig_url).await? {
+    let Some(extractor) = state.extractors.iter().find(|extractor| extractor.matches(&url)) else {
+        return Err(Error::Failure(StatusCode::NOT_FOUND));
+    };
+
+    let version = ExtractorVersion::of(&**extractor);
+
+    let miss = match state.cache.get(&orig_url, &version).await? {
         CacheHit::Hit(embed) => return Ok(embed),
         CacheHit::Miss(miss) => miss,
         CacheHit::Pending(mut rx) => loop {
@@ -185,23 +295,26 @@ async fn inner(
         },
     };
 
-    for extractor in &state.extractors {
-        if !extractor.matches(&url) {
-            continue;
+    // another instance may already be fetching this
+    let lease = match state.cache.single_flight(&orig_url, &version).await {
+        SingleFlight::Fetch(lease) => lease,
+        SingleFlight::Fetched(embed) => {
+            state.cache.fill(orig_url, miss, embed.clone()).await;
+
+            return Ok(embed);
         }
+    };
 
-        let cached = match extractor.extract(state.clone(), url, params).await {
-            Ok(embed) => CacheState::Ready(TArc::new(embed)),
-            Err(e) => CacheState::Errored(TArc::new(CacheError::new(e))),
-        };
+    let cached = match extractor.extract(state.clone(), url, params).await {
+        Ok(embed) => CacheState::Ready(TArc::new(embed)),
+        Err(e) => CacheState::Errored(TArc::new(CacheError::new(e))),
+    };
 
-        state.cache.put(orig_url, miss, cached.clone()).await;
+    state.cache.put(orig_url, miss, cached.clone(), version).await;
+    state.cache.release(lease).await;
 
-        match cached {
-            CacheState::Ready(embed) => return Ok(embed),
-            CacheState::Errored(err) => return Err(Error::CacheError(err)),
-        }
+    match cached {
+        CacheState::Ready(embed) => Ok(embed),
+        CacheState::Errored(err) => Err(Error::CacheError(err)),
     }
-
-    Err(Error::Failure(StatusCode::NOT_FOUND))
 }
@@ -0,0 +1,169 @@
+//! Character encoding detection, so pages and feeds not in UTF-8 can still be parsed.
+//!
+//! The encoding is taken from the first of:
+//! 1. The `charset` parameter of the `Content-Type` header
+//! 2. A byte-order mark
+//! 3. `<meta charset>` or `<meta http-equiv="Content-Type">` for HTML, or `<?xml encoding="">` for XML
+//! 4. Statistical detection, unless the document is already valid UTF-8
+
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+use encoding_rs::{Encoding, UTF_8};
+use regex::bytes::Regex;
+
+/// How far into the document to look for a declared encoding. The HTML spec only looks at the
+/// first 1024 bytes, but plenty of pages have too much junk before their `<meta charset>`.
+const PRESCAN_LIMIT: usize = 4096;
+
+/// Covers both `<meta charset="">` and `<meta http-equiv="Content-Type" content="text/html; charset=">`
+static META_CHARSET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i-u)<meta[^>]+charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#).unwrap());
+
+static XML_ENCODING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?-u)\A(?:\xEF\xBB\xBF)?\s*<\?xml[^>]+?encoding\s*=\s*["']([A-Za-z0-9._\-]+)["']"#).unwrap()
+});
+
+/// Get the `charset` parameter of a `Content-Type` header
+pub fn content_type_charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+
+        key.trim().eq_ignore_ascii_case("charset").then(|| super::trim_quotes(value))
+    })
+}
+
+fn prescan(re: &Regex, bytes: &[u8]) -> Option<&'static Encoding> {
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LIMIT)];
+
+    Encoding::for_label(re.captures(bytes)?.get(1)?.as_bytes())
+}
+
+fn detect(
+    charset: Option<&str>,
+    declared: Option<&'static Encoding>,
+    bytes: &[u8],
+    tld: Option<&str>,
+) -> &'static Encoding {
+    if let Some(encoding) = charset.and_then(|charset| Encoding::for_label(charset.as_bytes())) {
+        return encoding;
+    }
+
+    // the BOM is part of the bytes themselves, so is more reliable than anything written in them
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    // a document can't declare itself as UTF-16 in ASCII, so that must be wrong
+    if let Some(encoding) = declared {
+        return encoding.output_encoding();
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+
+    let tld = tld.and_then(|domain| domain.rsplit('.').next()).map(|tld| tld.to_ascii_lowercase());
+
+    detector.guess(tld.as_deref().map(str::as_bytes), true)
+}
+
+fn decode<'a>(encoding: &'static Encoding, bytes: &'a [u8]) -> Cow<'a, str> {
+    if encoding != UTF_8 {
+        log::debug!("Decoding document from {}", encoding.name());
+    }
+
+    encoding.decode_with_bom_removal(bytes).0
+}
+
+/// Decode an HTML document into UTF-8, given the `Content-Type` charset and domain it came from
+pub fn decode_html<'a>(charset: Option<&str>, bytes: &'a [u8], domain: Option<&str>) -> Cow<'a, str> {
+    let encoding = detect(charset, prescan(&META_CHARSET, bytes), bytes, domain);
+
+    decode(encoding, bytes)
+}
+
+/// Decode an XML document into UTF-8, given the `Content-Type` charset and domain it came from.
+///
+/// The XML declaration is rewritten to match, so XML parsers don't try to decode it again.
+pub fn decode_xml<'a>(charset: Option<&str>, bytes: &'a [u8], domain: Option<&str>) -> Cow<'a, [u8]> {
+    let encoding = detect(charset, prescan(&XML_ENCODING, bytes), bytes, domain);
+
+    let text = decode(encoding, bytes);
+
+    // find it again in the decoded text, which may have shifted
+    let range = match XML_ENCODING.captures(text.as_bytes()).and_then(|c| c.get(1)) {
+        Some(declaration) if declaration.as_bytes() != b"UTF-8" => declaration.range(),
+        _ => {
+            return match text {
+                Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+            }
+        }
+    };
+
+    let mut text = text.into_owned().into_bytes();
+    text.splice(range, b"UTF-8".iter().copied());
+
+    Cow::Owned(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_html() {
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("<title>日本語のタイトル</title>");
+
+        let mut page =
+            br#"<html><head><meta http-equiv="Content-Type" content="text/html; charset=Shift_JIS">"#
+                .to_vec();
+        page.extend_from_slice(&sjis);
+
+        assert!(decode_html(None, &page, None).ends_with("<title>日本語のタイトル</title>"));
+
+        // header takes priority over the document
+        let (cp1251, _, _) = encoding_rs::WINDOWS_1251.encode("<title>Заголовок</title>");
+        assert_eq!(
+            decode_html(Some("windows-1251"), &cp1251, None),
+            "<title>Заголовок</title>"
+        );
+
+        // unlabeled, but still detected
+        let (cp1251, _, _) =
+            encoding_rs::WINDOWS_1251.encode("<title>Съешь же ещё этих мягких французских булок</title>");
+        assert_eq!(
+            decode_html(None, &cp1251, Some("example.ru")),
+            "<title>Съешь же ещё этих мягких французских булок</title>"
+        );
+
+        // byte-order mark takes priority over a wrong declaration
+        let bom = "\u{FEFF}<meta charset=\"iso-8859-1\"><title>Café</title>";
+        assert_eq!(
+            decode_html(None, bom.as_bytes(), None),
+            "<meta charset=\"iso-8859-1\"><title>Café</title>"
+        );
+
+        assert_eq!(
+            content_type_charset("text/html; charset=\"EUC-KR\""),
+            Some("EUC-KR")
+        );
+    }
+
+    #[test]
+    fn test_decode_xml() {
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252
+            .encode(r#"<?xml version="1.0" encoding="ISO-8859-1"?><title>Café</title>"#);
+
+        let xml = decode_xml(None, &latin1, None);
+
+        assert_eq!(
+            &*xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?><title>Café</title>"#.as_bytes()
+        );
+    }
+}
@@ -1,9 +1,9 @@
-use smol_str::SmolStr;
+use std::borrow::Cow;
 
 use embed::*;
-use thin_str::ThinString;
+use timestamp::Timestamp;
 
-use super::html::{Header, LinkType, MetaProperty, Scope};
+use super::html::{Header, Link, LinkType, MetaProperty};
 use super::oembed::{OEmbed, OEmbedFormat, OEmbedLink, OEmbedType};
 
 #[derive(Debug, Default)]
@@ -11,6 +11,24 @@ pub struct ExtraFields<'a> {
     pub max_age: Option<u64>,
     pub link: Option<OEmbedLink<'a>>,
     pub manifest: Option<String>,
+    pub published: Option<Timestamp>,
+    pub modified: Option<Timestamp>,
+}
+
+impl ExtraFields<'_> {
+    pub fn into_owned(self) -> ExtraFields<'static> {
+        ExtraFields {
+            max_age: self.max_age,
+            link: self.link.map(|link| OEmbedLink {
+                url: Cow::Owned(link.url.into_owned()),
+                title: link.title.map(|title| Cow::Owned(title.into_owned())),
+                format: link.format,
+            }),
+            manifest: self.manifest,
+            published: self.published,
+            modified: self.modified,
+        }
+    }
 }
 
 pub fn parse_color(color: &str) -> Option<u32> {
@@ -23,60 +41,109 @@ pub fn parse_color(color: &str) -> Option<u32> {
     }
 }
 
-#[allow(dead_code)] // until we add anything with VideoObject
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum PossibleScopes {
-    Author,
-    Video,
+enum OgKind {
+    Image = 0,
+    Video = 1,
+    Audio = 2,
 }
 
-impl PossibleScopes {
-    pub fn id(self) -> &'static str {
-        match self {
-            PossibleScopes::Author => "author",
-            PossibleScopes::Video => "video",
+/// Open Graph structured properties, where each `og:image`, `og:video` or `og:audio` starts a new
+/// item, and the following `:width`/`:height`/`:alt`/`:type` tags attach to that item.
+///
+/// Multiple images are kept in `embed.imgs`, while multiple videos or audio become alternatives
+/// of the first, as clients can only play one anyway.
+struct OgItems {
+    max: usize,
+    started: [bool; 3],
+    /// Index of the item properties currently attach to, or `None` if over the limit
+    current: [Option<usize>; 3],
+}
+
+impl OgItems {
+    fn new(max: usize) -> Self {
+        OgItems {
+            max: max.max(1),
+            started: [false; 3],
+            // allow properties before the first item, which isn't uncommon
+            current: [Some(0); 3],
         }
     }
 
-    pub fn ty(self) -> &'static str {
-        match self {
-            PossibleScopes::Author => "Person",
-            PossibleScopes::Video => "VideoObject",
-        }
+    fn len(embed: &EmbedV1, kind: OgKind) -> usize {
+        let media = match kind {
+            OgKind::Image => return embed.imgs.len(),
+            OgKind::Video => &embed.video,
+            OgKind::Audio => &embed.audio,
+        };
+
+        media.as_ref().map_or(0, |media| 1 + media.alts.len())
     }
-}
 
-fn is_scope(scope: &Option<Scope>, p: PossibleScopes) -> bool {
-    if let Some(scope) = scope {
-        if matches!(scope.id, Some(ref id) if id == p.id())
-            || matches!(scope.prop, Some(ref prop) if prop == p.id())
-        {
-            return true;
+    fn item<'e>(embed: &'e mut EmbedV1, kind: OgKind, idx: usize) -> &'e mut BasicEmbedMedia {
+        let media = match kind {
+            OgKind::Image => {
+                while embed.imgs.len() <= idx {
+                    embed.imgs.push(EmbedMedia::default());
+                }
+
+                return &mut embed.imgs[idx].media;
+            }
+            OgKind::Video => embed.video.get_or_insert_with(Default::default),
+            OgKind::Audio => embed.audio.get_or_insert_with(Default::default),
+        };
+
+        if idx == 0 {
+            return &mut media.media;
+        }
+
+        while media.alts.len() < idx {
+            media.alts.push(BasicEmbedMedia::default());
         }
 
-        if let Some(ref ty) = scope.ty {
-            if ty.contains(p.ty()) {
-                return true;
+        &mut media.alts[idx - 1]
+    }
+
+    /// Start a new item with the given URL
+    fn start(&mut self, embed: &mut EmbedV1, kind: OgKind, url: &str) {
+        let k = kind as usize;
+
+        let current = match self.current[k] {
+            // the first item replaces anything else, such as a `twitter:image`
+            _ if !self.started[k] => Some(0),
+            Some(idx) if Self::item(embed, kind, idx).url.is_empty() => Some(idx),
+            _ => {
+                let len = Self::len(embed, kind);
+                let exists = (0..len).any(|idx| *Self::item(embed, kind, idx).url == *url);
+
+                (!exists && len < self.max).then_some(len)
             }
+        };
+
+        self.started[k] = true;
+        self.current[k] = current;
+
+        if let Some(idx) = current {
+            Self::item(embed, kind, idx).url = url.into();
         }
     }
 
-    false
+    /// Get the current item to attach properties to, if not over the limit
+    fn get<'e>(&self, embed: &'e mut EmbedV1, kind: OgKind) -> Option<&'e mut BasicEmbedMedia> {
+        Some(Self::item(embed, kind, self.current[kind as usize]?))
+    }
 }
 
-// #[derive(Debug)]
-// struct Image<'a> {
-//     url: Cow<'a, str>,
-//     width: Option<u32>,
-//     height: Option<u32>,
-//     mime: Option<Cow<'a, str>>,
-// }
-
 /// Build an initial embed profile from HTML meta tags
 ///
 /// NOTE: HEADERS MUST BE SORTED BY PROPERTY NAME FOR OPTIMAL RESULTS
-pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> ExtraFields<'a> {
+pub fn parse_meta_to_embed<'a>(
+    embed: &mut EmbedV1,
+    headers: &[Header<'a>],
+    max_images: usize,
+) -> ExtraFields<'a> {
     let mut extra = ExtraFields::default();
+    let mut og = OgItems::new(max_images);
 
     #[derive(Default, Clone, Copy)]
     struct Misc<'a> {
@@ -84,8 +151,24 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
         data: Option<&'a str>,
     }
 
+    /// `twitter:player` card, only applied after all tags are seen since they can come in any order
+    #[derive(Default)]
+    struct Player<'a> {
+        url: Option<&'a str>,
+        width: Option<i32>,
+        height: Option<i32>,
+        stream: Option<&'a str>,
+        stream_mime: Option<&'a str>,
+    }
+
     let mut misc: [Misc; 4] = [Misc::default(); 4];
+    let mut article = false;
+    let mut apple_icon = None;
+    let mut section = None;
+    let mut tags: Vec<&str> = Vec::new();
+    let mut player = Player::default();
     let mut max_dim = 0;
+    let mut json_ld = Vec::new();
     //let mut images = Vec::new();
 
     macro_rules! get {
@@ -106,6 +189,12 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
     for header in headers {
         match header {
             Header::Meta(meta) => {
+                if meta.pty == MetaProperty::ItemProp
+                    && super::microdata::parse_item_prop(embed, &mut extra, headers, meta)
+                {
+                    continue;
+                }
+
                 #[rustfmt::skip]
                 macro_rules! raw_content { () => { From::from(meta.content.as_ref()) }; }
                 #[rustfmt::skip]
@@ -130,19 +219,6 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
                     "og:url" => embed.canonical = content!(),
                     "title" | "og:title" | "twitter:title" => embed.title = content!(),
 
-                    // YouTube uses this schema at least
-                    "name"
-                        if meta.pty == MetaProperty::ItemProp
-                            && is_scope(&meta.scope, PossibleScopes::Author) =>
-                    {
-                        get!(author).name = raw_content!();
-                    }
-                    "url"
-                        if meta.pty == MetaProperty::ItemProp
-                            && is_scope(&meta.scope, PossibleScopes::Author) =>
-                    {
-                        get!(author).url = content!();
-                    }
                     "dc:creator" | "article:author" | "book:author" => get!(author).name = raw_content!(),
 
                     // don't let the twitter image overwrite og images
@@ -152,30 +228,68 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
                         _ => {}
                     },
 
-                    "og:image" | "og:image:url" | "og:image:secure_url" => get_img!().url = raw_content!(),
-                    "og:video" | "og:video:url" | "og:video:secure_url" => get!(video).url = raw_content!(),
-                    "og:audio" | "og:audio:url" | "og:audio:secure_url" => get!(audio).url = raw_content!(),
-
-                    "og:image:width" => get_img!().width = content_int(),
-                    "og:video:width" => get!(video).width = content_int(),
-                    "music:duration" => get!(audio).width = content_int(),
-
-                    "og:image:height" => get_img!().height = content_int(),
-                    "og:video:height" => get!(video).height = content_int(),
-
-                    "og:image:type" => get_img!().mime = content!(),
-                    "og:video:type" => get!(video).mime = content!(),
-                    "og:audio:type" => get!(audio).mime = content!(),
+                    "og:type" => article |= meta.content.starts_with("article"),
 
-                    "og:image:alt" => get_img!().description = content!(),
-                    "og:video:alt" => get!(video).description = content!(),
-                    "og:audio:alt" => get!(audio).description = content!(),
+                    "article:published_time" => {
+                        article = true;
+                        extra.published = Timestamp::parse(meta.content.trim()).or(extra.published);
+                    }
+                    "article:modified_time" | "og:updated_time" => {
+                        extra.modified = Timestamp::parse(meta.content.trim()).or(extra.modified);
+                    }
+                    "article:section" => {
+                        article = true;
+                        section = Some(meta.content.trim());
+                    }
+                    "article:tag" => {
+                        article = true;
+
+                        // some sites list all tags in a single property
+                        for tag in meta.content.split(',').map(str::trim) {
+                            if !tag.is_empty() && !tags.contains(&tag) {
+                                tags.push(tag);
+                            }
+                        }
+                    }
 
                     "og:ttl" => match content_int() {
                         None => {}
                         Some(ttl) => extra.max_age = Some(ttl as u64),
                     },
 
+                    "og:image" => og.start(embed, OgKind::Image, &meta.content),
+                    "og:video" => og.start(embed, OgKind::Video, &meta.content),
+                    "og:audio" => og.start(embed, OgKind::Audio, &meta.content),
+
+                    "music:duration" => get!(audio).width = content_int(),
+
+                    prop if prop.starts_with("og:") => {
+                        let Some((kind, prop)) = prop[3..].split_once(':') else {
+                            continue;
+                        };
+
+                        let kind = match kind {
+                            "image" => OgKind::Image,
+                            "video" => OgKind::Video,
+                            "audio" => OgKind::Audio,
+                            _ => continue,
+                        };
+
+                        let Some(media) = og.get(embed, kind) else {
+                            continue; // over the limit
+                        };
+
+                        match prop {
+                            // explicit URLs of the current item, not a new item
+                            "url" | "secure_url" => media.url = raw_content!(),
+                            "width" => media.width = content_int(),
+                            "height" => media.height = content_int(),
+                            "type" => media.mime = content!(),
+                            "alt" => media.description = content!(),
+                            _ => {}
+                        }
+                    }
+
                     "twitter:label1" | "twitter:label2" | "twitter:label3" | "twitter:label4" => {
                         let idx = meta.property.as_bytes()[meta.property.len() - 1] - b'0';
                         misc[idx as usize - 1].label = Some(&meta.content);
@@ -186,6 +300,12 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
                         misc[idx as usize - 1].data = Some(&meta.content);
                     }
 
+                    "twitter:player" => player.url = Some(&meta.content),
+                    "twitter:player:width" => player.width = content_int(),
+                    "twitter:player:height" => player.height = content_int(),
+                    "twitter:player:stream" => player.stream = Some(&meta.content),
+                    "twitter:player:stream:content_type" => player.stream_mime = Some(&meta.content),
+
                     _ if meta.property.eq_ignore_ascii_case("rating") => parse_rating(embed, &meta.content),
 
                     "isFamilyFriendly" => {
@@ -246,6 +366,14 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
                 media.url = link.href.as_ref().into();
                 media.mime = link.mime.as_ref().map(|m| From::from(m.as_ref()));
             }
+            Header::Link(link) if link.rel == LinkType::AppleTouchIcon => {
+                // prefer the largest, as they're usually all a reasonable size
+                let size = |link: &Link| link.sizes.map_or(0, |[w, h]| w.max(h));
+
+                if apple_icon.is_none_or(|icon| size(link) > size(icon)) {
+                    apple_icon = Some(link);
+                }
+            }
             Header::Link(link) if link.rel == LinkType::Canonical => {
                 embed.canonical = Some(link.href.as_ref().into());
             }
@@ -280,6 +408,7 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
                     }
                 }
             }
+            Header::JsonLd(json) => json_ld.push(json.as_ref()),
             _ => {}
         }
     }
@@ -294,6 +423,73 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
         }
     }
 
+    // the player is an iframe, and the optional stream is the raw media file
+    if let Some(url) = player.url {
+        if EmbedMedia::is_empty(&embed.obj) {
+            let obj = get!(obj);
+
+            obj.url = url.into();
+            obj.mime = Some(From::from("text/html"));
+            obj.width = player.width;
+            obj.height = player.height;
+        }
+    }
+
+    if let Some(stream) = player.stream {
+        // og:video takes priority, unless it's just a player itself
+        let video = get!(video);
+
+        if video.url.is_empty() || matches!(video.mime, Some(ref mime) if mime.starts_with("text/html")) {
+            video.url = stream.into();
+            video.mime = player.stream_mime.map(From::from);
+            video.width = player.width;
+            video.height = player.height;
+        }
+    }
+
+    // after the meta tags, so they take priority
+    if !json_ld.is_empty() {
+        let published = super::jsonld::parse_json_ld_to_embed(embed, &json_ld);
+
+        extra.published = extra.published.or(published);
+    }
+
+    if let (None, Some(link)) = (&embed.provider.icon, apple_icon) {
+        let media = get!(provider.icon);
+
+        media.url = link.href.as_ref().into();
+        media.mime = link.mime.as_ref().map(|m| From::from(m.as_ref()));
+
+        if let Some([w, h]) = link.sizes {
+            media.width = (w > 0).then_some(w as i32);
+            media.height = (h > 0).then_some(h as i32);
+        }
+    }
+
+    if let Some(section) = section.filter(|s| !s.is_empty()) {
+        embed.fields.push(EmbedField {
+            name: From::from("Section"),
+            value: From::from(section),
+            ..EmbedField::default()
+        });
+    }
+
+    if !tags.is_empty() {
+        // reasonable limit for embedding
+        let tags = tags[..tags.len().min(8)].join(", ");
+
+        embed.fields.push(EmbedField {
+            name: From::from("Tags"),
+            value: From::from(tags.as_str()),
+            ..EmbedField::default()
+        });
+    }
+
+    // images are common on articles, but don't hide any playable media
+    if article && matches!(embed.ty, EmbedType::Link | EmbedType::Img) {
+        embed.ty = EmbedType::Article;
+    }
+
     determine_embed_type(embed);
 
     extra
@@ -301,7 +497,10 @@ pub fn parse_meta_to_embed<'a>(embed: &mut EmbedV1, headers: &[Header<'a>]) -> E
 
 pub(crate) fn determine_embed_type(embed: &mut EmbedV1) {
     if embed.imgs.iter().any(|img| !img.url.is_empty()) {
-        embed.ty = EmbedType::Img;
+        // articles usually have images, but are still articles
+        if embed.ty != EmbedType::Article {
+            embed.ty = EmbedType::Img;
+        }
     } else if embed.ty == EmbedType::Img {
         embed.ty = EmbedType::Link;
     }
@@ -344,6 +543,8 @@ pub fn parse_oembed_to_embed(embed: &mut EmbedV1, mut o: OEmbed) -> ExtraFields
         OEmbedType::Photo => EmbedType::Img,
         OEmbedType::Video => EmbedType::Vid,
         OEmbedType::Rich => EmbedType::Html,
+        // link is the least specific type, so don't lose anything better from the meta tags
+        OEmbedType::Link if embed.ty == EmbedType::Article => EmbedType::Article,
         OEmbedType::Link => EmbedType::Link,
         OEmbedType::Unknown => embed.ty,
     };
@@ -373,38 +574,38 @@ pub fn parse_oembed_to_embed(embed: &mut EmbedV1, mut o: OEmbed) -> ExtraFields
         determine_embed_type(embed);
     }
 
-    let media = match o.kind {
-        OEmbedType::Photo => {
-            embed.imgs.push(EmbedMedia::default());
-            embed.imgs.first_mut().unwrap()
-        }
-        OEmbedType::Video => &mut **get!(video),
-        _ => &mut **get!(obj),
+    // only a single iframe is accepted, anything else is ignored rather than guessed at
+    let iframe = match o.html {
+        Some(ref html) if o.kind != OEmbedType::Photo => super::iframe::parse_iframe(html),
+        _ => None,
     };
 
-    let mut mime = media.mime.take();
-    let mut overwrite = false;
+    if let Some(mut iframe) = iframe {
+        iframe.fit(o.width.map(|x| x.0 as _), o.height.map(|x| x.0 as _));
 
-    if let Some(html) = o.html {
-        match mime {
-            Some(ref mime) if mime == "text/html" => {}
-            _ => {
-                if let Some(src) = parse_embed_html_src(&html) {
-                    media.url = src;
-                    mime = Some(parse_embed_html_type(&html).unwrap_or(SmolStr::new_inline("text/html")));
-                    overwrite = true;
-                }
-            }
-        }
+        let obj = get!(obj);
+
+        obj.mime = Some(iframe.mime());
+        obj.url = iframe.src;
+        obj.width = iframe.width;
+        obj.height = iframe.height;
+
+        embed.ty = EmbedType::Html;
     } else if let Some(url) = o.url {
-        media.url = url;
-        mime = None; // unknown
-        overwrite = true;
-    }
+        let media = match o.kind {
+            OEmbedType::Photo => {
+                if embed.imgs.is_empty() {
+                    embed.imgs.push(EmbedMedia::default());
+                }
 
-    media.mime = mime;
+                embed.imgs.first_mut().unwrap()
+            }
+            OEmbedType::Video => &mut **get!(video),
+            _ => &mut **get!(obj),
+        };
 
-    if overwrite {
+        media.url = url;
+        media.mime = None; // unknown
         media.width = o.width.map(|x| x.0 as _);
         media.height = o.height.map(|x| x.0 as _);
     }
@@ -439,65 +640,10 @@ impl<T> OptionExt<T> for Option<T> {
     }
 }
 
-fn parse_embed_html_src(html: &str) -> Option<ThinString> {
-    let mut start = memchr::memmem::find(html.as_bytes(), b"src=\"http")?;
-
-    // strip src=" prefix
-    start += "src=\"".len();
-
-    let end = start + memchr::memchr(b'"', &html.as_bytes()[start..])?;
-
-    let src = &html[start..end];
-
-    memchr::memmem::find(src.as_bytes(), b"://")?;
-
-    Some(From::from(src))
-}
-
-fn parse_embed_html_type(html: &str) -> Option<SmolStr> {
-    let mut start = memchr::memmem::find(html.as_bytes(), b"type=\"")?;
-
-    start += "type=\"".len(); // strip prefix
-
-    let end = start + memchr::memchr(b'"', &html.as_bytes()[start..])?;
-
-    let ty = &html[start..end];
-
-    // mime type e.g.: image/png
-    memchr::memchr(b'/', ty.as_bytes())?;
-
-    Some(From::from(ty))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_parse_embed_html() {
-        let fixture = "<object width=\"425\" height=\"344\">
-        <param name=\"movie\" value=\"https://www.youtube.com/v/M3r2XDceM6A&fs=1\"></param>
-        <param name=\"allowFullScreen\" value=\"true\"></param>
-        <param name=\"allowscriptaccess\" value=\"always\"></param>
-        <embed src=\"https://www.youtube.com/v/M3r2XDceM6A&fs=1\"
-            type=\"application/x-shockwave-flash\" width=\"425\" height=\"344\"
-            allowscriptaccess=\"always\" allowfullscreen=\"true\"></embed>
-        </object>";
-
-        let src = parse_embed_html_src(fixture);
-        let ty = parse_embed_html_type(fixture);
-
-        assert_eq!(
-            src.as_ref().map::<&str, _>(|s| s),
-            Some("https://www.youtube.com/v/M3r2XDceM6A&fs=1")
-        );
-
-        assert_eq!(
-            ty.as_ref().map::<&str, _>(|s| s),
-            Some("application/x-shockwave-flash")
-        );
-    }
-
     #[test]
     fn test_parse_color() {
         let colors = [
@@ -508,4 +654,103 @@ mod tests {
             assert_eq!(parse_color(&format!("#{color:06x}")), Some(color));
         }
     }
+
+    #[test]
+    fn test_multiple_og_media() {
+        let fixture = r#"
+            <meta property="og:image:width" content="100">
+            <meta property="og:image" content="https://example.com/1.jpg">
+            <meta property="og:image:height" content="200">
+            <meta property="og:image" content="https://example.com/2.jpg">
+            <meta property="og:image:secure_url" content="https://example.com/2-secure.jpg">
+            <meta property="og:image:alt" content="Second">
+            <meta property="og:image" content="https://example.com/1.jpg">
+            <meta property="og:image:width" content="999">
+            <meta property="og:image" content="https://example.com/3.jpg">
+            <meta property="og:image" content="https://example.com/4.jpg">
+            <meta property="og:image:width" content="300">
+            <meta property="og:video" content="https://example.com/video.webm">
+            <meta property="og:video:type" content="video/webm">
+            <meta property="og:video" content="https://example.com/video.mp4">
+            <meta property="og:video:type" content="video/mp4">"#;
+
+        let headers = crate::parser::html::parse_meta(fixture, false).0;
+
+        let mut embed = EmbedV1::default();
+        parse_meta_to_embed(&mut embed, &headers, 2);
+
+        assert_eq!(embed.imgs.len(), 2);
+        assert_eq!(&*embed.imgs[0].url, "https://example.com/1.jpg");
+        assert_eq!(
+            (embed.imgs[0].width, embed.imgs[0].height),
+            (Some(100), Some(200))
+        );
+        assert_eq!(&*embed.imgs[1].url, "https://example.com/2-secure.jpg");
+        assert_eq!(embed.imgs[1].description.as_deref(), Some("Second"));
+        assert_eq!(embed.imgs[1].width, None);
+
+        let video = embed.video.as_ref().unwrap();
+        assert_eq!(video.mime.as_deref(), Some("video/webm"));
+        assert_eq!(video.alts.len(), 1);
+        assert_eq!(&*video.alts[0].url, "https://example.com/video.mp4");
+        assert_eq!(video.alts[0].mime.as_deref(), Some("video/mp4"));
+    }
+
+    #[test]
+    fn test_article() {
+        let fixture = r#"
+            <meta property="og:type" content="article">
+            <meta property="og:image" content="https://example.com/lead.jpg">
+            <meta property="article:published_time" content="2024-03-01T12:00:00+01:00">
+            <meta property="og:updated_time" content="2024-03-02T08:30:00Z">
+            <meta property="article:section" content="Science">
+            <meta property="article:tag" content="Space">
+            <meta property="article:tag" content="Astronomy, Space">"#;
+
+        let headers = crate::parser::html::parse_meta(fixture, false).0;
+
+        let mut embed = EmbedV1::default();
+        let extra = parse_meta_to_embed(&mut embed, &headers, 4);
+
+        assert_eq!(embed.ty, EmbedType::Article);
+        assert_eq!(extra.published, Timestamp::parse("2024-03-01T11:00:00Z"));
+        assert_eq!(extra.modified, Timestamp::parse("2024-03-02T08:30:00Z"));
+
+        assert_eq!(embed.fields.len(), 2);
+        assert_eq!(embed.fields[0].value, "Science");
+        assert_eq!(embed.fields[1].value, "Space, Astronomy");
+    }
+
+    #[test]
+    fn test_twitter_player() {
+        let fixture = r#"
+            <meta name="twitter:card" content="player">
+            <meta name="twitter:player:stream" content="https://example.com/clip.mp4">
+            <meta name="twitter:player" content="https://example.com/embed/clip">
+            <meta name="twitter:player:width" content="1280">
+            <meta name="twitter:player:height" content="720">
+            <meta name="twitter:player:stream:content_type" content="video/mp4">"#;
+
+        let headers = crate::parser::html::parse_meta(fixture, false).0;
+
+        let mut embed = EmbedV1::default();
+        parse_meta_to_embed(&mut embed, &headers, 4);
+
+        let obj = embed.obj.as_ref().unwrap();
+        assert_eq!(&*obj.url, "https://example.com/embed/clip");
+        assert_eq!(obj.mime.as_deref(), Some("text/html"));
+        assert_eq!((obj.width, obj.height), (Some(1280), Some(720)));
+
+        let video = embed.video.as_ref().unwrap();
+        assert_eq!(&*video.url, "https://example.com/clip.mp4");
+        assert_eq!(video.mime.as_deref(), Some("video/mp4"));
+
+        assert_eq!(embed.ty, EmbedType::Html);
+
+        // without the player, such as if HTML isn't allowed, the stream is still playable
+        embed.obj = None;
+        determine_embed_type(&mut embed);
+
+        assert_eq!(embed.ty, EmbedType::Vid);
+    }
 }
@@ -1,8 +1,25 @@
-use feed_rs::model::{Feed, Image};
+use feed_rs::model::{Entry, Feed, Image, MediaContent, MediaObject};
+use url::Url;
 
+use embed::timestamp::{Duration, Timestamp};
 use embed::*;
 
-pub fn feed_into_embed(embed: &mut EmbedV1, feed: Feed) -> u64 {
+/// Options for [`feed_into_embed`]
+#[derive(Debug, Clone, Copy)]
+pub struct FeedOptions {
+    /// Maximum number of entries to include as fields
+    pub max_entries: usize,
+    /// Write timestamps as markdown
+    pub markdown: bool,
+}
+
+/// Build an embed from the feed, returning the max age in seconds.
+///
+/// `xml` is the raw document, used for anything not parsed by `feed_rs`. If the `url` fragment
+/// is the id of an entry, such as a podcast episode, the embed is built for that entry instead.
+pub fn feed_into_embed(embed: &mut EmbedV1, feed: Feed, url: &Url, xml: &[u8], options: FeedOptions) -> u64 {
+    let channel = channel_xml(xml);
+
     embed.title = feed.title.map(|t| t.content.into());
     embed.description = feed.description.map(|t| t.content.into());
 
@@ -21,7 +38,106 @@ pub fn feed_into_embed(embed: &mut EmbedV1, feed: Feed) -> u64 {
         }
     }
 
-    60 * feed.ttl.unwrap_or(15) as u64
+    if let Some(person) = feed.authors.into_iter().next() {
+        embed.author = Some(EmbedAuthor {
+            name: person.name.into(),
+            url: person.uri.map(Into::into),
+            ..EmbedAuthor::default()
+        });
+    }
+
+    let mut entries = feed.entries;
+
+    // newest first, keeping the feed order for anything without a date
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry_timestamp(entry)));
+
+    let selected = url.fragment().and_then(|id| entries.iter().find(|entry| entry.id == id));
+
+    match selected {
+        Some(entry) => {
+            embed.provider.name = embed.title.take().map(|title| From::from(&*title));
+            embed.title = entry.title.as_ref().map(|t| t.content.as_str().into());
+
+            if let Some(summary) = entry.summary.as_ref() {
+                embed.description = Some(crate::util::trim_text(&summary.content).into());
+            }
+
+            if let Some(link) = entry.links.first() {
+                embed.url = Some(link.href.as_str().into());
+            }
+        }
+        None => {
+            for entry in entries.iter().take(options.max_entries) {
+                if let Some(field) = entry_to_field(entry, options.markdown) {
+                    embed.fields.push(field);
+                }
+            }
+        }
+    }
+
+    // podcasts have the latest episode playable, unless a specific one was linked to
+    let episode = match selected {
+        Some(entry) => enclosure(entry).map(|e| (entry, e)),
+        None => entries.iter().find_map(|entry| enclosure(entry).map(|e| (entry, e))),
+    };
+
+    if let Some((entry, (media, content))) = episode {
+        podcast_into_embed(embed, entry, media, content, channel);
+    }
+
+    match feed.ttl {
+        Some(ttl) => 60 * ttl as u64,
+        None => update_period(channel).unwrap_or(60 * 15),
+    }
+}
+
+/// Find the audio enclosure of an entry, along with the media object it's part of
+fn enclosure(entry: &Entry) -> Option<(&MediaObject, &MediaContent)> {
+    entry.media.iter().find_map(|media| {
+        let content = media.content.iter().find(|content| {
+            content.url.is_some()
+                && content.content_type.as_ref().is_some_and(|mime| mime.to_string().starts_with("audio/"))
+        })?;
+
+        Some((media, content))
+    })
+}
+
+fn podcast_into_embed(
+    embed: &mut EmbedV1,
+    entry: &Entry,
+    media: &MediaObject,
+    content: &MediaContent,
+    channel: &[u8],
+) {
+    let Some(ref url) = content.url else { return };
+
+    let audio = embed.audio.get_or_insert_with(Default::default);
+
+    audio.url = url.as_str().into();
+    audio.mime = content.content_type.as_ref().map(|mime| mime.to_string().into());
+    audio.description = entry.title.as_ref().map(|t| t.content.as_str().into());
+
+    if let Some(duration) = media.duration.or(content.duration) {
+        super::utils::push_duration_field(embed, &super::utils::format_seconds(duration.as_secs()));
+    }
+
+    // channel artwork, which feed_rs only uses as the logo if there wasn't already one
+    if let Some(artwork) = tag_attr(channel, "itunes:image", "href") {
+        let thumb = embed.thumb.get_or_insert_with(Default::default);
+
+        thumb.url = artwork.into();
+        thumb.description = None;
+    }
+
+    // feed_rs only recognizes `true`, but older feeds use `yes` or `explicit`
+    if let Some(explicit) = tag_text(channel, "itunes:explicit") {
+        if ["true", "yes", "explicit"].iter().any(|e| explicit.eq_ignore_ascii_case(e)) {
+            embed.flags |= EmbedFlags::ADULT;
+        }
+    }
+
+    embed.ty = EmbedType::Audio;
 }
 
 fn image_to_media(media: &mut EmbedMedia, image: Image) {
@@ -30,3 +146,259 @@ fn image_to_media(media: &mut EmbedMedia, image: Image) {
     media.width = image.width.map(|x| x as i32);
     media.height = image.height.map(|x| x as i32);
 }
+
+/// When the entry was published or last updated
+fn entry_timestamp(entry: &Entry) -> Option<Timestamp> {
+    let ts = entry.published.or(entry.updated)?;
+
+    Timestamp::UNIX_EPOCH.checked_add(Duration::milliseconds(ts.timestamp_millis()))
+}
+
+/// Field with the entry title as its name, and its date and link as the value
+fn entry_to_field(entry: &Entry, markdown: bool) -> Option<EmbedField> {
+    let title = entry.title.as_ref().map(|t| crate::util::trim_text(&t.content))?;
+
+    if title.is_empty() {
+        return None;
+    }
+
+    let mut value = String::new();
+
+    if let Some(ts) = entry_timestamp(entry) {
+        crate::util::write_timestamp(&mut value, ts, markdown).ok()?;
+    }
+
+    if let Some(link) = entry.links.first() {
+        if !value.is_empty() {
+            value.push('\n');
+        }
+
+        value.push_str(&link.href);
+    }
+
+    Some(EmbedField {
+        name: From::from(super::trim_text(&title, 256)),
+        value: From::from(value.as_str()),
+        ..EmbedField::default()
+    })
+}
+
+/// The part of the document before any items or entries, which describes the feed itself
+fn channel_xml(xml: &[u8]) -> &[u8] {
+    let end = [&b"<item"[..], b"<entry"]
+        .iter()
+        .filter_map(|tag| memchr::memmem::find(xml, tag))
+        .min()
+        .unwrap_or(xml.len());
+
+    &xml[..end]
+}
+
+/// Text content of the first `<tag>`, which must not contain any markup
+fn tag_text<'a>(xml: &'a [u8], tag: &str) -> Option<&'a str> {
+    let start = memchr::memmem::find(xml, format!("<{tag}>").as_bytes())? + tag.len() + 2;
+    let end = start + memchr::memchr(b'<', &xml[start..])?;
+
+    std::str::from_utf8(&xml[start..end]).ok().map(str::trim)
+}
+
+/// Value of an attribute on the first `<tag ...>`
+fn tag_attr<'a>(xml: &'a [u8], tag: &str, attr: &str) -> Option<&'a str> {
+    let start = memchr::memmem::find(xml, format!("<{tag} ").as_bytes())? + tag.len() + 2;
+    let end = start + memchr::memchr(b'>', &xml[start..])?;
+
+    let attrs = std::str::from_utf8(&xml[start..end]).ok()?;
+    let pattern = format!("{attr}=");
+
+    // skip matches within other attributes, like `href=` in `data-href=`
+    let (idx, _) = attrs
+        .match_indices(&pattern)
+        .find(|&(idx, _)| idx == 0 || attrs.as_bytes()[idx - 1].is_ascii_whitespace())?;
+
+    let value = &attrs[idx + pattern.len()..];
+
+    let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+
+    value[1..].split(quote).next()
+}
+
+/// Parse the `sy:updatePeriod` and `sy:updateFrequency` tags from the RSS syndication module, in seconds
+fn update_period(xml: &[u8]) -> Option<u64> {
+    let period: u64 = match tag_text(xml, "sy:updatePeriod")? {
+        "hourly" => 60 * 60,
+        "daily" => 60 * 60 * 24,
+        "weekly" => 60 * 60 * 24 * 7,
+        "monthly" => 60 * 60 * 24 * 30,
+        "yearly" => 60 * 60 * 24 * 365,
+        _ => return None,
+    };
+
+    // number of updates per period
+    let frequency = tag_text(xml, "sy:updateFrequency").and_then(|f| f.parse().ok()).unwrap_or(1u64).max(1);
+
+    Some(period / frequency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_into_embed() {
+        let fixture = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/">
+        <channel>
+            <title>Example Blog</title>
+            <link>https://example.com/</link>
+            <description>Posts about things</description>
+            <sy:updatePeriod>daily</sy:updatePeriod>
+            <sy:updateFrequency>4</sy:updateFrequency>
+            <item>
+                <title>Older post</title>
+                <link>https://example.com/older</link>
+                <pubDate>Mon, 01 Jan 2024 12:00:00 GMT</pubDate>
+            </item>
+            <item>
+                <title>Newest post</title>
+                <link>https://example.com/newest</link>
+                <pubDate>Wed, 03 Jan 2024 12:00:00 GMT</pubDate>
+            </item>
+            <item>
+                <title>Middle post</title>
+                <link>https://example.com/middle</link>
+                <pubDate>Tue, 02 Jan 2024 12:00:00 GMT</pubDate>
+            </item>
+        </channel>
+        </rss>"#;
+
+        let feed = feed_rs::parser::parse(fixture.as_bytes()).unwrap();
+
+        let mut embed = EmbedV1::default();
+
+        let options = FeedOptions {
+            max_entries: 2,
+            markdown: false,
+        };
+
+        let url = Url::parse("https://example.com/feed.xml").unwrap();
+
+        let max_age = feed_into_embed(&mut embed, feed, &url, fixture.as_bytes(), options);
+
+        assert_eq!(max_age, 60 * 60 * 6);
+        assert_eq!(embed.title.as_deref(), Some("Example Blog"));
+
+        assert_eq!(embed.fields.len(), 2);
+        assert_eq!(&*embed.fields[0].name, "Newest post");
+        assert_eq!(
+            &*embed.fields[0].value,
+            "2024-01-03T12:00:00.000Z\nhttps://example.com/newest"
+        );
+        assert_eq!(&*embed.fields[1].name, "Middle post");
+
+        assert_eq!(
+            update_period(b"<sy:updatePeriod>hourly</sy:updatePeriod>"),
+            Some(60 * 60)
+        );
+        assert_eq!(update_period(b"<ttl>60</ttl>"), None);
+    }
+
+    #[test]
+    fn test_multibyte_title() {
+        // 3-byte characters, so the limit falls within one
+        let title = "日本語のタイトル".repeat(12);
+
+        let fixture = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="2.0"><channel>
+                <title>Example Blog</title>
+                <item><title>{title}</title><link>https://example.com/post</link></item>
+            </channel></rss>"#
+        );
+
+        let feed = feed_rs::parser::parse(fixture.as_bytes()).unwrap();
+
+        let options = FeedOptions {
+            max_entries: 5,
+            markdown: false,
+        };
+
+        let mut embed = EmbedV1::default();
+        let url = Url::parse("https://example.com/feed.xml").unwrap();
+
+        feed_into_embed(&mut embed, feed, &url, fixture.as_bytes(), options);
+
+        assert_eq!(embed.fields.len(), 1);
+        assert!(embed.fields[0].name.len() <= 256);
+        assert!(title.starts_with(&*embed.fields[0].name));
+    }
+
+    #[test]
+    fn test_podcast() {
+        let fixture = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+        <channel>
+            <title>Example Podcast</title>
+            <description>Talking about things</description>
+            <image><url>https://example.com/small.png</url><title>Logo</title><link>https://example.com/</link></image>
+            <itunes:image data-href="https://example.com/wrong.jpg" href="https://example.com/artwork.jpg"/>
+            <itunes:author>Jane Doe</itunes:author>
+            <itunes:explicit>yes</itunes:explicit>
+            <item>
+                <title>Episode 2</title>
+                <guid isPermaLink="false">ep-2</guid>
+                <pubDate>Wed, 03 Jan 2024 12:00:00 GMT</pubDate>
+                <enclosure url="https://cdn.example.com/ep2.mp3" length="1234" type="audio/mpeg"/>
+                <itunes:duration>1:02:03</itunes:duration>
+            </item>
+            <item>
+                <title>Episode 1</title>
+                <guid isPermaLink="false">ep-1</guid>
+                <description>The first one</description>
+                <link>https://example.com/episodes/1</link>
+                <pubDate>Mon, 01 Jan 2024 12:00:00 GMT</pubDate>
+                <enclosure url="https://cdn.example.com/ep1.m4a" length="1234" type="audio/x-m4a"/>
+                <itunes:duration>95</itunes:duration>
+            </item>
+        </channel>
+        </rss>"#;
+
+        let options = FeedOptions {
+            max_entries: 5,
+            markdown: false,
+        };
+
+        let mut embed = EmbedV1::default();
+        let url = Url::parse("https://example.com/podcast.xml").unwrap();
+        let feed = feed_rs::parser::parse(fixture.as_bytes()).unwrap();
+
+        feed_into_embed(&mut embed, feed, &url, fixture.as_bytes(), options);
+
+        let audio = embed.audio.as_ref().unwrap();
+        assert_eq!(&*audio.url, "https://cdn.example.com/ep2.mp3");
+        assert_eq!(audio.mime.as_deref(), Some("audio/mpeg"));
+        assert_eq!(
+            &*embed.thumb.as_ref().unwrap().url,
+            "https://example.com/artwork.jpg"
+        );
+        assert_eq!(embed.author.as_ref().map(|a| &*a.name), Some("Jane Doe"));
+        assert!(embed.flags.contains(EmbedFlags::ADULT));
+        assert_eq!(embed.ty, EmbedType::Audio);
+        assert!(embed.fields.iter().any(|f| f.name == "Duration" && f.value == "1:02:03"));
+
+        // link to a specific episode
+        let mut embed = EmbedV1::default();
+        let url = Url::parse("https://example.com/podcast.xml#ep-1").unwrap();
+        let feed = feed_rs::parser::parse(fixture.as_bytes()).unwrap();
+
+        feed_into_embed(&mut embed, feed, &url, fixture.as_bytes(), options);
+
+        assert_eq!(embed.title.as_deref(), Some("Episode 1"));
+        assert_eq!(embed.description.as_deref(), Some("The first one"));
+        assert_eq!(embed.provider.name.as_deref(), Some("Example Podcast"));
+        assert_eq!(
+            &*embed.audio.as_ref().unwrap().url,
+            "https://cdn.example.com/ep1.m4a"
+        );
+        assert!(embed.fields.iter().any(|f| f.name == "Duration" && f.value == "1:35"));
+    }
+}
@@ -1,5 +1,7 @@
 use std::borrow::Cow;
 
+use super::tokenizer::{Token, Tokenizer};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MetaProperty {
     Name,
@@ -20,6 +22,8 @@ pub enum LinkType {
     //DnsPrefetch,
     //Help,
     Icon,
+    /// Only used if there's no regular icon
+    AppleTouchIcon,
     License,
     Shortlink,
     //Stylesheet,
@@ -31,7 +35,8 @@ pub struct Meta<'a> {
     pub content: Cow<'a, str>,
     pub pty: MetaProperty,
     pub property: Cow<'a, str>,
-    pub scope: Option<Scope<'a>>,
+    /// Index of the enclosing [`Header::Scope`] in the header list
+    pub scope: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -45,11 +50,24 @@ pub struct Link<'a> {
     pub crossorigin: Option<Cow<'a, str>>,
 }
 
+/// Microdata `itemscope`, which may be nested within another
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Scope<'a> {
     pub id: Option<Cow<'a, str>>,
     pub ty: Option<Cow<'a, str>>,
     pub prop: Option<Cow<'a, str>>,
+    /// Index of the enclosing [`Header::Scope`] in the header list
+    pub parent: Option<usize>,
+}
+
+impl Scope<'_> {
+    /// The schema.org type name, e.g. `VideoObject` for `http://schema.org/VideoObject`
+    pub fn type_name(&self) -> Option<&str> {
+        // itemtype can contain multiple types, so just use the first
+        let ty = self.ty.as_deref()?.split_ascii_whitespace().next()?.trim_end_matches('/');
+
+        Some(ty.rsplit('/').next().unwrap_or(ty))
+    }
 }
 
 impl Meta<'_> {
@@ -69,6 +87,8 @@ pub enum Header<'a> {
     Meta(Meta<'a>),
     Link(Link<'a>),
     Scope(Scope<'a>),
+    /// Contents of a `<script type="application/ld+json">` block
+    JsonLd(Cow<'a, str>),
 }
 
 impl Header<'_> {
@@ -76,180 +96,472 @@ impl Header<'_> {
         match self {
             Header::Meta(meta) => meta.is_valid(),
             Header::Link(link) => link.is_valid(),
-            Header::Scope(_) => false,
+            // kept regardless, as other headers refer to them by index
+            Header::Scope(_) => true,
+            Header::JsonLd(json) => !json.is_empty(),
         }
     }
 }
 
 pub type HeaderList<'a> = smallvec::SmallVec<[Header<'a>; 32]>;
 
-pub use super::regexes::{ATTRIBUTE_RE, META_TAGS};
+/// Elements that never have contents or a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track",
+    "wbr",
+];
 
-/// Returns `None` on invalid HTML
-pub fn parse_meta<'a>(input: &'a str) -> Option<HeaderList<'a>> {
-    let mut res = HeaderList::<'a>::default();
-    let mut scope = None;
+/// An `itemscope` element that hasn't been closed yet
+struct OpenScope<'a> {
+    tag: &'a str,
+    /// Number of nested elements with the same tag name
+    depth: u32,
+    /// Index of the [`Header::Scope`] in the header list
+    idx: usize,
+}
 
-    for m in META_TAGS.find_iter(input) {
-        let (mut start, mut tag_end) = (m.start(), m.end());
+/// What the next text is for
+enum Pending<'a> {
+    None,
+    Title,
+    JsonLd,
+    /// `<span itemprop="name">Text</span>`
+    ItemProp(Cow<'a, str>, Option<usize>),
+}
 
-        // detect tag type and initialize header value
-        let mut header = match input.get(start..tag_end) {
-            Some("<meta ") => Header::Meta(Meta {
-                content: "".into(), // deferred
+/// Builds the header list from tags, regardless of whether they came from the tokenizer or a DOM
+struct HeaderBuilder<'a> {
+    res: HeaderList<'a>,
+    open: smallvec::SmallVec<[OpenScope<'a>; 8]>,
+    pending: Pending<'a>,
+}
+
+fn is(name: &str, tags: &[&str]) -> bool {
+    tags.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+}
+
+impl<'a> HeaderBuilder<'a> {
+    fn new() -> Self {
+        HeaderBuilder {
+            res: HeaderList::new(),
+            open: smallvec::SmallVec::new(),
+            pending: Pending::None,
+        }
+    }
+
+    fn push(&mut self, header: Header<'a>) {
+        if header.is_valid() {
+            self.res.push(header);
+        }
+    }
+
+    fn scope(&self) -> Option<usize> {
+        self.open.last().map(|s| s.idx)
+    }
+
+    fn start_tag(&mut self, name: &'a str, attrs: impl Iterator<Item = (&'a str, Cow<'a, str>)>, void: bool) {
+        self.pending = Pending::None;
+
+        if name.eq_ignore_ascii_case("meta") {
+            let mut meta = Meta {
+                content: "".into(),
                 pty: MetaProperty::Property,
                 property: "".into(),
-                scope: scope.clone(),
-            }),
-            // special case, parse `<title>Title</title>`
-            Some(tag) if tag.starts_with("<title") => {
-                let title_start = tag_end;
-
-                if let Some(title_end) = memchr::memmem::find(input[title_start..].as_bytes(), b"</title>") {
-                    res.push(Header::Meta(Meta {
-                        content: input[title_start..(title_start + title_end)].trim().into(),
-                        pty: MetaProperty::Title,
-                        property: "".into(),
-                        scope: scope.clone(),
-                    }));
+                scope: self.scope(),
+            };
+
+            for (key, value) in attrs {
+                meta.pty = match key {
+                    _ if is(key, &["content", "href"]) => {
+                        meta.content = value;
+                        continue;
+                    }
+                    _ if "name".eq_ignore_ascii_case(key) => MetaProperty::Name,
+                    _ if "property".eq_ignore_ascii_case(key) => MetaProperty::Property,
+                    _ if "description".eq_ignore_ascii_case(key) => MetaProperty::Description,
+
+                    // I've seen multiple cases of this around...
+                    _ if "itemprop".eq_ignore_ascii_case(key) => MetaProperty::ItemProp,
+
+                    _ => continue,
+                };
+
+                meta.property = value;
+            }
+
+            return self.push(Header::Meta(meta));
+        }
+
+        if name.eq_ignore_ascii_case("link") {
+            return self.link(attrs);
+        }
+
+        if name.eq_ignore_ascii_case("title") {
+            self.pending = Pending::Title;
+            return;
+        }
+
+        if name.eq_ignore_ascii_case("script") {
+            if attrs.filter(|(key, _)| key.eq_ignore_ascii_case("type")).any(|(_, ty)| ty.contains("ld+json"))
+            {
+                self.pending = Pending::JsonLd;
+            }
+
+            return;
+        }
+
+        self.element(name, attrs, void);
+    }
+
+    fn link(&mut self, attrs: impl Iterator<Item = (&'a str, Cow<'a, str>)>) {
+        let mut link = Link {
+            href: "".into(),
+            rel: LinkType::None,
+            ty: None,
+            title: None,
+            mime: None,
+            sizes: None,
+            crossorigin: None,
+        };
+
+        let mut itemprop = None;
+        let mut content = None;
+        let mut property = None;
+
+        for (key, value) in attrs {
+            match key {
+                _ if "href".eq_ignore_ascii_case(key) => link.href = value,
+                _ if "type".eq_ignore_ascii_case(key) => link.ty = Some(value),
+                _ if "title".eq_ignore_ascii_case(key) => link.title = Some(value),
+                _ if "crossorigin".eq_ignore_ascii_case(key) => link.crossorigin = Some(value),
+                _ if "rel".eq_ignore_ascii_case(key) => {
+                    link.rel = match &*value {
+                        "alternate" => LinkType::Alternate,
+                        "canonical" => LinkType::Canonical,
+                        "external" => LinkType::External,
+                        "license" => LinkType::License,
+                        "shortlink" => LinkType::Shortlink,
+                        "manifest" => LinkType::Manifest,
+                        "icon" | "shortcut icon" => LinkType::Icon,
+                        "apple-touch-icon" | "apple-touch-icon-precomposed" => LinkType::AppleTouchIcon,
+                        _ => continue,
+                    };
                 }
+                _ if "sizes".eq_ignore_ascii_case(key) => {
+                    link.sizes = Some({
+                        let mut sizes = [0; 2];
+
+                        for dim in value.split('x').take(2).map(|d| d.parse()).enumerate() {
+                            if let (idx, Ok(value)) = dim {
+                                sizes[idx] = value;
+                            }
+                        }
 
-                continue;
+                        sizes
+                    });
+                }
+                // weird, convert to meta
+                _ if "itemprop".eq_ignore_ascii_case(key) => itemprop = Some(value),
+                _ if "content".eq_ignore_ascii_case(key) => content = Some(value),
+                _ if "name".eq_ignore_ascii_case(key) => property = Some((MetaProperty::Name, value)),
+                _ if "property".eq_ignore_ascii_case(key) => property = Some((MetaProperty::Property, value)),
+                _ => continue,
             }
-            Some("<link ") => Header::Link(Link {
-                href: "".into(),
-                rel: LinkType::None,
-                ty: None,
-                title: None,
-                mime: None,
-                sizes: None,
-                crossorigin: None,
-            }),
-            Some(etc) => {
-                if etc.starts_with("<div ") {
-                    tag_end = start + 4;
-                } else if etc.starts_with("<span ") {
-                    tag_end = start + 5;
-                } else {
-                    continue;
+        }
+
+        if let Some(property) = itemprop {
+            return self.push(Header::Meta(Meta {
+                content: content.unwrap_or(link.href),
+                pty: MetaProperty::ItemProp,
+                property,
+                scope: self.scope(),
+            }));
+        }
+
+        // also weird, someone meant to write `<meta>`
+        if let Some(content) = content {
+            let (pty, property) = property.unwrap_or((MetaProperty::Property, "".into()));
+
+            return self.push(Header::Meta(Meta {
+                content,
+                pty,
+                property,
+                scope: self.scope(),
+            }));
+        }
+
+        if matches!(link.rel, LinkType::Icon | LinkType::AppleTouchIcon) {
+            link.mime.clone_from(&link.ty);
+        } else {
+            link.sizes = None;
+        }
+
+        self.push(Header::Link(link));
+    }
+
+    /// Any other element, which may be part of microdata
+    fn element(&mut self, name: &'a str, attrs: impl Iterator<Item = (&'a str, Cow<'a, str>)>, void: bool) {
+        let mut scope = Scope::default();
+        let mut itemscope = false;
+        let (mut content, mut value) = (None, None);
+
+        for (key, v) in attrs {
+            match key {
+                _ if "itemscope".eq_ignore_ascii_case(key) => itemscope = true,
+                _ if "itemid".eq_ignore_ascii_case(key) => scope.id = Some(v),
+                _ if "itemtype".eq_ignore_ascii_case(key) => scope.ty = Some(v),
+                _ if "itemprop".eq_ignore_ascii_case(key) => scope.prop = Some(v),
+                _ if "content".eq_ignore_ascii_case(key) => content = Some(v),
+
+                // https://html.spec.whatwg.org/multipage/microdata.html#values
+                _ if "href".eq_ignore_ascii_case(key) && is(name, &["a", "area"]) => value = Some(v),
+                _ if "src".eq_ignore_ascii_case(key)
+                    && is(
+                        name,
+                        &["img", "audio", "video", "source", "iframe", "embed", "track"],
+                    ) =>
+                {
+                    value = Some(v)
                 }
+                _ if "data".eq_ignore_ascii_case(key) && is(name, &["object"]) => value = Some(v),
+                _ if "datetime".eq_ignore_ascii_case(key) && is(name, &["time"]) => value = Some(v),
+                _ if "value".eq_ignore_ascii_case(key) && is(name, &["data", "meter"]) => value = Some(v),
+                _ => {}
+            }
+        }
+
+        let parent = self.scope();
 
-                Header::Scope(Scope::default())
+        if itemscope {
+            scope.parent = parent;
+
+            if !void {
+                self.open.push(OpenScope {
+                    tag: name,
+                    depth: 0,
+                    idx: self.res.len(),
+                });
             }
-            _ => continue,
-        };
 
-        start = tag_end; // skip to end of opening tag
+            // kept regardless, as other headers refer to them by index
+            self.res.push(Header::Scope(scope));
+
+            return;
+        }
+
+        if !void {
+            if let Some(top) = self.open.last_mut().filter(|top| top.tag.eq_ignore_ascii_case(name)) {
+                top.depth += 1;
+            }
+        }
 
-        // find end of tag, like <meta whatever="" >
-        let end = match memchr::memchr(b'>', input[start..].as_bytes()) {
-            Some(end) => end + start,
-            None => continue,
+        let Some(property) = scope.prop else {
+            return;
         };
-        let meta_inner = &input[start..end];
-
-        // name="" content=""
-        for m in ATTRIBUTE_RE.find_iter(meta_inner) {
-            let part = m.as_str();
-
-            // name=""
-            if let Some((left, right)) = part.split_once('=') {
-                let value = html_escape::decode_html_entities(super::trim_quotes(right));
-
-                match header {
-                    Header::Meta(ref mut meta) => {
-                        meta.pty = match left {
-                            "content" | "href" => {
-                                meta.content = value;
-                                continue;
-                            }
-                            "name" => MetaProperty::Name,
-                            "property" => MetaProperty::Property,
-                            "description" => MetaProperty::Description,
 
-                            // I've seen multiple cases of this around...
-                            _ if "itemprop".eq_ignore_ascii_case(left) => MetaProperty::ItemProp,
+        match content.or(value) {
+            Some(content) => self.push(Header::Meta(Meta {
+                content,
+                pty: MetaProperty::ItemProp,
+                property,
+                scope: parent,
+            })),
+            None if !void => self.pending = Pending::ItemProp(property, parent),
+            None => {}
+        }
+    }
 
-                            _ => continue,
-                        };
+    fn end_tag(&mut self, name: &str) {
+        self.pending = Pending::None;
 
-                        meta.property = value;
-                    }
-                    Header::Scope(ref mut scope) => match left {
-                        _ if "itemid".eq_ignore_ascii_case(left) => scope.id = Some(value),
-                        _ if "itemtype".eq_ignore_ascii_case(left) => scope.ty = Some(value),
-                        _ if "itemprop".eq_ignore_ascii_case(left) => scope.prop = Some(value),
-                        _ => continue,
-                    },
-                    Header::Link(ref mut link) => match left {
-                        "href" => link.href = value,
-                        "type" => link.ty = Some(value),
-                        "title" => link.title = Some(value),
-                        "crossorigin" => link.crossorigin = Some(value),
-                        "rel" => {
-                            link.rel = match &*value {
-                                "alternate" => LinkType::Alternate,
-                                "canonical" => LinkType::Canonical,
-                                "external" => LinkType::External,
-                                "license" => LinkType::License,
-                                "shortlink" => LinkType::Shortlink,
-                                "manifest" => LinkType::Manifest,
-                                "icon" | "shortcut icon" | "apple-touch-icon" => LinkType::Icon,
-                                _ => continue,
-                            };
-                        }
-                        // weird, convert to meta
-                        _ if "itemprop".eq_ignore_ascii_case(left) => {
-                            header = Header::Meta(Meta {
-                                content: link.href.clone(),
-                                pty: MetaProperty::ItemProp,
-                                property: value,
-                                scope: scope.clone(),
-                            });
-                        }
-                        // weird, convert to meta
-                        "content" => {
-                            header = Header::Meta(Meta {
-                                content: value,
-                                pty: MetaProperty::Property,
-                                property: "".into(),
-                                scope: scope.clone(),
-                            });
-                        }
-                        _ if link.rel == LinkType::Icon => match left {
-                            "sizes" => {
-                                link.sizes = Some({
-                                    let mut sizes = [0; 2];
-
-                                    for dim in value.split('x').take(2).map(|d| d.parse()).enumerate() {
-                                        if let (idx, Ok(value)) = dim {
-                                            sizes[idx] = value;
-                                        }
-                                    }
-
-                                    sizes
-                                });
-                            }
-                            "type" => link.mime = Some(value),
-                            _ => continue,
-                        },
-                        _ => continue,
-                    },
+        // only nesting within the innermost scope matters
+        if let Some(top) = self.open.last_mut().filter(|top| top.tag.eq_ignore_ascii_case(name)) {
+            match top.depth {
+                0 => {
+                    self.open.pop();
                 }
+                _ => top.depth -= 1,
             }
         }
+    }
+
+    /// Text following the last tag, and whether it still needs HTML entities to be decoded
+    fn text(&mut self, text: &'a str, encoded: bool) {
+        let decode = |text: &'a str| match encoded {
+            true => html_escape::decode_html_entities(text.trim()),
+            false => Cow::Borrowed(text.trim()),
+        };
+
+        match std::mem::replace(&mut self.pending, Pending::None) {
+            Pending::None => {}
+            Pending::Title => self.push(Header::Meta(Meta {
+                content: decode(text),
+                pty: MetaProperty::Title,
+                property: "".into(),
+                scope: self.scope(),
+            })),
+            Pending::JsonLd => {
+                let json = text.trim();
 
-        if let Header::Scope(new_scope) = header {
-            scope = Some(new_scope);
-            continue;
+                // some sites wrap it in a CDATA section or HTML comment for old browsers
+                let json = json
+                    .strip_prefix("<![CDATA[")
+                    .and_then(|json| json.strip_suffix("]]>"))
+                    .or_else(|| json.strip_prefix("<!--").and_then(|json| json.strip_suffix("-->")))
+                    .unwrap_or(json);
+
+                self.push(Header::JsonLd(json.trim().into()));
+            }
+            // whitespace before any nested element doesn't count
+            Pending::ItemProp(property, scope) if text.trim().is_empty() => {
+                self.pending = Pending::ItemProp(property, scope);
+            }
+            Pending::ItemProp(property, scope) => self.push(Header::Meta(Meta {
+                content: decode(text),
+                pty: MetaProperty::ItemProp,
+                property,
+                scope,
+            })),
         }
+    }
+}
 
-        if header.is_valid() {
-            res.push(header);
+/// Parse the headers of an HTML document in a single pass.
+///
+/// With `head_only`, only microdata and JSON-LD are taken from the `<body>`, as any other
+/// `<meta>` or `<link>` there is usually from embedded widgets. Returns the headers and
+/// the byte offset parsing stopped at.
+pub fn parse_meta(input: &str, head_only: bool) -> (HeaderList<'_>, usize) {
+    let mut builder = HeaderBuilder::new();
+    let mut tokens = Tokenizer::new(input);
+    let mut in_body = false;
+
+    for token in tokens.by_ref() {
+        match token {
+            Token::StartTag(tag) => {
+                if head_only && tag.name.eq_ignore_ascii_case("body") {
+                    in_body = true;
+                }
+
+                if in_body
+                    && is(tag.name, &["meta", "link", "title"])
+                    && !tag.attrs().any(|(key, _)| key.eq_ignore_ascii_case("itemprop"))
+                {
+                    continue;
+                }
+
+                let void = tag.self_closing || is(tag.name, VOID_ELEMENTS);
+
+                builder.start_tag(tag.name, tag.attrs(), void);
+            }
+            Token::EndTag(name) => {
+                if head_only && name.eq_ignore_ascii_case("head") {
+                    in_body = true;
+                }
+
+                builder.end_tag(name);
+            }
+            Token::Text(text) => builder.text(text, true),
+        }
+    }
+
+    (builder.res, tokens.offset())
+}
+
+/// Collect the headers from an already-parsed document, such as the one used for [`SiteFieldSelectors`](crate::config::selectors::SiteFieldSelectors)
+pub fn parse_dom(doc: &scraper::Html) -> HeaderList<'_> {
+    use ego_tree::iter::Edge;
+    use scraper::Node;
+
+    let mut builder = HeaderBuilder::new();
+
+    for edge in doc.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(el) => {
+                    let attrs = el.attrs().map(|(key, value)| (key, Cow::Borrowed(value)));
+
+                    builder.start_tag(el.name(), attrs, is(el.name(), VOID_ELEMENTS));
+                }
+                Node::Text(text) => builder.text(&**text, false),
+                _ => {}
+            },
+            Edge::Close(node) => {
+                if let Node::Element(el) = node.value() {
+                    builder.end_tag(el.name());
+                }
+            }
         }
     }
 
-    //res.sort();
+    builder.res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meta() {
+        let fixture = r#"<!DOCTYPE html>
+        <html>
+        <head>
+            <title>Title &amp; More</title>
+            <!-- <meta property="og:title" content="Commented"> -->
+            <meta property="og:description" content="Contains > and &quot;quotes&quot;">
+            <script>document.write('<meta property="og:title" content="Fake">')</script>
+            <script type="application/ld+json">{"@type":"Article","headline":"JSON"}</script>
+            <link rel="icon" sizes="32x32" type="image/png" href="/favicon.png">
+        </head>
+        <body>
+            <meta property="og:image" content="https://example.com/body.png">
+        </body>
+        </html>"#;
+
+        let (headers, _) = parse_meta(fixture, true);
+
+        // the meta in the body isn't microdata, so is skipped
+        assert_eq!(headers.len(), 4);
+
+        assert!(
+            matches!(&headers[0], Header::Meta(meta) if meta.pty == MetaProperty::Title && meta.content == "Title & More")
+        );
+        assert!(matches!(&headers[1], Header::Meta(meta) if meta.content == r#"Contains > and "quotes""#));
+        assert!(matches!(&headers[2], Header::JsonLd(json) if json.starts_with('{')));
+        assert!(
+            matches!(&headers[3], Header::Link(link) if link.sizes == Some([32, 32]) && link.mime.as_deref() == Some("image/png"))
+        );
+
+        // the DOM should give the same results, plus the body
+        let doc = scraper::Html::parse_document(fixture);
+        let dom_headers = parse_dom(&doc);
+
+        assert_eq!(&dom_headers[..4], &headers[..]);
+        assert_eq!(dom_headers.len(), 5);
+    }
+
+    #[test]
+    fn test_malformed_meta() {
+        let fixture = r#"<head>
+            <meta property=”og:title” content=”Curly Title”>
+            <link property="og:description" content="Link as meta">
+            <META NAME="description" CONTENT="Uppercase">
+            <link REL=icon HREF=/favicon.ico>
+        </head>"#;
 
-    Some(res)
+        let (headers, _) = parse_meta(fixture, true);
+
+        assert!(
+            matches!(&headers[0], Header::Meta(meta) if meta.property == "og:title" && meta.content == "Curly Title")
+        );
+        assert!(
+            matches!(&headers[1], Header::Meta(meta) if meta.property == "og:description" && meta.content == "Link as meta")
+        );
+        assert!(
+            matches!(&headers[2], Header::Meta(meta) if meta.pty == MetaProperty::Name && meta.content == "Uppercase")
+        );
+        assert!(
+            matches!(&headers[3], Header::Link(link) if link.rel == LinkType::Icon && link.href == "/favicon.ico")
+        );
+    }
 }
@@ -0,0 +1,198 @@
+//! Sanitization of embeddable HTML, such as from oEmbed, which must consist of a single `<iframe>`.
+//!
+//! The iframe's `sandbox` and `allow` attributes are kept as parameters of the `text/html` MIME type,
+//! e.g. `text/html; sandbox="allow-scripts allow-same-origin"; allow="autoplay; fullscreen"`,
+//! filtered down to what's reasonable for an embed. Iframes without a `sandbox` attribute are given
+//! [`DEFAULT_SANDBOX`], so the lack of one is never taken as being unrestricted.
+
+use smol_str::SmolStr;
+
+use embed::thin_str::ThinString;
+
+/// Sandbox permissions an embed may be granted
+const SANDBOX: &[&str] = &[
+    "allow-scripts",
+    "allow-same-origin",
+    "allow-popups",
+    "allow-popups-to-escape-sandbox",
+    "allow-presentation",
+    "allow-forms",
+];
+
+/// Sandbox permissions for iframes that didn't specify any, enough for typical players
+pub const DEFAULT_SANDBOX: &str = "allow-scripts allow-same-origin allow-popups";
+
+/// Permissions-policy features an embed may request
+const ALLOW: &[&str] = &[
+    "autoplay",
+    "encrypted-media",
+    "fullscreen",
+    "picture-in-picture",
+    "clipboard-write",
+    "accelerometer",
+    "gyroscope",
+    "web-share",
+];
+
+/// Elements that can't appear anywhere in the HTML, as they could run outside of the iframe
+const FORBIDDEN: &[&str] = &[
+    "script", "object", "embed", "applet", "frame", "frameset", "base", "link", "meta", "form",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iframe {
+    pub src: ThinString,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub sandbox: String,
+    pub allow: Option<String>,
+}
+
+impl Iframe {
+    /// `text/html` with the sandbox and allow attributes as parameters
+    pub fn mime(&self) -> SmolStr {
+        let mut mime = format!("text/html; sandbox=\"{}\"", self.sandbox);
+
+        if let Some(ref allow) = self.allow {
+            mime += &format!("; allow=\"{allow}\"");
+        }
+
+        mime.into()
+    }
+
+    /// Fill in a missing dimension from the given size, keeping its aspect ratio
+    pub fn fit(&mut self, width: Option<i32>, height: Option<i32>) {
+        match (self.width, self.height, width, height) {
+            (None, None, w, h) => (self.width, self.height) = (w, h),
+            (Some(sw), None, Some(w), Some(h)) if w > 0 => self.height = Some(sw * h / w),
+            (None, Some(sh), Some(w), Some(h)) if h > 0 => self.width = Some(sh * w / h),
+            _ => {}
+        }
+    }
+}
+
+/// Only numeric pixel sizes are useful, not percentages
+fn dimension(value: Option<&str>) -> Option<i32> {
+    value?.trim().trim_end_matches("px").parse().ok().filter(|&d| d > 0)
+}
+
+/// Parse the HTML, accepting only a single `<iframe>` with an http(s) source.
+///
+/// Wrapper elements like `<div>` are ignored, but anything that could run outside
+/// of the iframe causes the whole thing to be rejected.
+pub fn parse_iframe(html: &str) -> Option<Iframe> {
+    let doc = scraper::Html::parse_fragment(html);
+
+    let mut iframe = None;
+
+    for el in doc.tree.nodes().filter_map(|node| node.value().as_element()) {
+        match el.name() {
+            "iframe" if iframe.is_none() => iframe = Some(el),
+            "iframe" => return None,
+            name if FORBIDDEN.contains(&name) => return None,
+            _ => {}
+        }
+    }
+
+    let iframe = iframe?;
+
+    let src = url::Url::parse(iframe.attr("src")?.trim()).ok()?;
+
+    if !matches!(src.scheme(), "https" | "http") {
+        return None;
+    }
+
+    let sandbox = match iframe.attr("sandbox") {
+        Some(sandbox) => sandbox
+            .split_ascii_whitespace()
+            .filter(|token| SANDBOX.contains(token))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => DEFAULT_SANDBOX.to_owned(),
+    };
+
+    let mut allow = Vec::new();
+
+    for directive in iframe.attr("allow").unwrap_or_default().split(';') {
+        // ignore any origins after the feature name
+        if let Some(feature) = directive.split_ascii_whitespace().next() {
+            if ALLOW.contains(&feature) && !allow.contains(&feature) {
+                allow.push(feature);
+            }
+        }
+    }
+
+    if iframe.attr("allowfullscreen").is_some() && !allow.contains(&"fullscreen") {
+        allow.push("fullscreen");
+    }
+
+    Some(Iframe {
+        src: src.as_str().into(),
+        width: dimension(iframe.attr("width")),
+        height: dimension(iframe.attr("height")),
+        sandbox,
+        allow: (!allow.is_empty()).then(|| allow.join("; ")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_iframe() {
+        let fixture = r#"<div style="padding:56.25% 0 0 0;position:relative;">
+            <iframe src="https://player.example.com/video/1?h=abc" width="640" height="100%"
+                frameborder="0" allow="autoplay; fullscreen; camera 'src'; microphone" allowfullscreen
+                sandbox="allow-scripts allow-same-origin allow-top-navigation"></iframe>
+        </div>"#;
+
+        let mut iframe = parse_iframe(fixture).unwrap();
+
+        assert_eq!(&*iframe.src, "https://player.example.com/video/1?h=abc");
+        assert_eq!(iframe.sandbox, "allow-scripts allow-same-origin");
+        assert_eq!(iframe.allow.as_deref(), Some("autoplay; fullscreen"));
+        assert_eq!(
+            iframe.mime(),
+            r#"text/html; sandbox="allow-scripts allow-same-origin"; allow="autoplay; fullscreen""#
+        );
+
+        iframe.fit(Some(1280), Some(720));
+
+        assert_eq!((iframe.width, iframe.height), (Some(640), Some(360)));
+    }
+
+    #[test]
+    fn test_default_sandbox() {
+        let iframe = parse_iframe(r#"<iframe src="https://example.com/embed"></iframe>"#).unwrap();
+
+        assert_eq!(iframe.sandbox, DEFAULT_SANDBOX);
+        assert_eq!(
+            iframe.mime(),
+            r#"text/html; sandbox="allow-scripts allow-same-origin allow-popups""#
+        );
+    }
+
+    #[test]
+    fn test_reject_html() {
+        let fixtures = [
+            // script alongside the iframe
+            r#"<iframe src="https://example.com/embed"></iframe><script src="https://example.com/player.js"></script>"#,
+            // multiple iframes
+            r#"<iframe src="https://example.com/1"></iframe><iframe src="https://example.com/2"></iframe>"#,
+            // not http(s)
+            r#"<iframe src="javascript:alert(1)"></iframe>"#,
+            // not an iframe at all
+            r#"<object width="425" height="344">
+                <param name="movie" value="https://www.youtube.com/v/M3r2XDceM6A&fs=1"></param>
+                <embed src="https://www.youtube.com/v/M3r2XDceM6A&fs=1"
+                    type="application/x-shockwave-flash" width="425" height="344"></embed>
+            </object>"#,
+            r#"<blockquote class="twitter-tweet"><a href="https://twitter.com/x/status/1"></a></blockquote>"#,
+        ];
+
+        for fixture in fixtures {
+            assert_eq!(parse_iframe(fixture), None, "{fixture}");
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! JSON-LD structured data, from `<script type="application/ld+json">` blocks
+//!
+//! Only fills in what the meta tags didn't provide, so meta tags always take priority.
+
+use hashbrown::HashMap;
+use serde_json::Value;
+
+use embed::timestamp::Timestamp;
+use embed::*;
+
+/// Schema.org types describing the page itself, in order of preference
+const PRIMARY_TYPES: &[&[&str]] = &[
+    &["VideoObject", "Movie", "TVEpisode", "Clip"],
+    &[
+        "Article",
+        "NewsArticle",
+        "ReportageNewsArticle",
+        "AnalysisNewsArticle",
+        "BlogPosting",
+        "TechArticle",
+        "ScholarlyArticle",
+        "SocialMediaPosting",
+        "DiscussionForumPosting",
+        "Recipe",
+        "Product",
+        "Event",
+        "Book",
+        "MusicRecording",
+        "MusicAlbum",
+        "PodcastEpisode",
+        "Review",
+        "ImageObject",
+    ],
+    &[
+        "WebPage",
+        "ItemPage",
+        "AboutPage",
+        "ProfilePage",
+        "CollectionPage",
+        "QAPage",
+    ],
+];
+
+struct Graph<'a> {
+    nodes: Vec<&'a Value>,
+    ids: HashMap<&'a str, &'a Value>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(values: &'a [Value]) -> Self {
+        let mut graph = Graph {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+        };
+
+        for value in values {
+            graph.collect(value);
+        }
+
+        graph
+    }
+
+    /// Flatten top-level arrays and `@graph` into a list of typed nodes
+    fn collect(&mut self, value: &'a Value) {
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    self.collect(item);
+                }
+            }
+            Value::Object(obj) => {
+                if let Some(graph) = obj.get("@graph") {
+                    self.collect(graph);
+                }
+
+                if !obj.contains_key("@type") {
+                    return;
+                }
+
+                if let Some(Value::String(id)) = obj.get("@id") {
+                    self.ids.insert(id.as_str(), value);
+                }
+
+                self.nodes.push(value);
+            }
+            _ => {}
+        }
+    }
+
+    /// Follow `{"@id": "..."}` references to the full node, if present
+    fn resolve(&self, value: &'a Value) -> &'a Value {
+        match value {
+            Value::Object(obj) if !obj.contains_key("@type") => match obj.get("@id") {
+                Some(Value::String(id)) => self.ids.get(id.as_str()).copied().unwrap_or(value),
+                _ => value,
+            },
+            _ => value,
+        }
+    }
+
+    /// Resolve the first value of a property, which may be a single value or an array
+    fn first(&self, node: &'a Value, key: &str) -> Option<&'a Value> {
+        match node.get(key)? {
+            Value::Array(items) => items.first().map(|item| self.resolve(item)),
+            value => Some(self.resolve(value)),
+        }
+    }
+
+    fn primary(&self) -> Option<&'a Value> {
+        PRIMARY_TYPES.iter().find_map(|tys| self.nodes.iter().copied().find(|node| has_type(node, tys)))
+    }
+
+    fn find(&self, tys: &[&str]) -> Option<&'a Value> {
+        self.nodes.iter().copied().find(|node| has_type(node, tys))
+    }
+}
+
+fn has_type(node: &Value, tys: &[&str]) -> bool {
+    match node.get("@type") {
+        Some(Value::String(ty)) => tys.contains(&ty.as_str()),
+        Some(Value::Array(types)) => {
+            types.iter().any(|ty| matches!(ty, Value::String(ty) if tys.contains(&ty.as_str())))
+        }
+        _ => false,
+    }
+}
+
+fn text(value: &Value) -> Option<&str> {
+    let text = match value {
+        Value::String(s) => s.trim(),
+        Value::Array(items) => return items.first().and_then(text),
+        _ => return None,
+    };
+
+    (!text.is_empty()).then_some(text)
+}
+
+/// Dimensions may be numbers, strings like `"1200"` or `"1200 px"`, or a `QuantitativeValue`
+fn dimension(value: Option<&Value>) -> Option<i32> {
+    match value? {
+        Value::Number(n) => n.as_f64().map(|n| n as i32),
+        Value::String(s) => s.trim().trim_end_matches("px").trim().parse().ok(),
+        value @ Value::Object(_) => dimension(value.get("value")),
+        _ => None,
+    }
+}
+
+/// Parse an image from either a plain URL or an `ImageObject`
+fn media(value: &Value) -> Option<EmbedMedia> {
+    let mut media = EmbedMedia::default();
+
+    match value {
+        Value::String(_) => media.url = text(value)?.into(),
+        Value::Object(_) => {
+            let url = ["url", "contentUrl"].iter().find_map(|key| value.get(*key).and_then(text))?;
+
+            media.url = url.into();
+            media.width = dimension(value.get("width"));
+            media.height = dimension(value.get("height"));
+            media.mime =
+                value.get("encodingFormat").and_then(text).filter(|m| m.contains('/')).map(From::from);
+            media.description = value.get("caption").and_then(text).map(From::from);
+        }
+        _ => return None,
+    }
+
+    Some(media)
+}
+
+/// Add JSON-LD data to the embed wherever the meta tags left it empty,
+/// returning the `datePublished` of the primary node, if any.
+pub fn parse_json_ld_to_embed(embed: &mut EmbedV1, blocks: &[&str]) -> Option<Timestamp> {
+    let values = blocks.iter().filter_map(|block| serde_json::from_str(block).ok()).collect::<Vec<Value>>();
+
+    let graph = Graph::new(&values);
+
+    let primary = graph.primary()?;
+
+    if embed.title.is_none() {
+        embed.title =
+            ["headline", "name"].iter().find_map(|key| primary.get(*key).and_then(text)).map(From::from);
+    }
+
+    if embed.description.is_none() {
+        if let Some(description) = primary.get("description").and_then(text) {
+            embed.description = Some(crate::util::trim_text(description).into());
+        }
+    }
+
+    if embed.author.is_none() {
+        match graph.first(primary, "author").or_else(|| graph.first(primary, "creator")) {
+            Some(author @ Value::Object(_)) => {
+                if let Some(name) = author.get("name").and_then(text) {
+                    let author_embed = embed.author.get_or_insert_with(Default::default);
+
+                    author_embed.name = name.into();
+                    author_embed.url = author.get("url").and_then(text).map(From::from);
+                }
+            }
+            Some(author) => {
+                if let Some(name) = text(author) {
+                    embed.author.get_or_insert_with(Default::default).name = name.into();
+                }
+            }
+            None => {}
+        }
+    }
+
+    if let Some(publisher) = graph.first(primary, "publisher") {
+        if embed.provider.name.is_none() {
+            embed.provider.name = publisher.get("name").and_then(text).map(From::from);
+        }
+
+        if embed.provider.icon.is_none() {
+            embed.provider.icon = graph.first(publisher, "logo").and_then(media).map(Box::new);
+        }
+    }
+
+    if embed.imgs.iter().all(|img| img.url.is_empty()) {
+        if let Some(img) = graph.first(primary, "image").and_then(media) {
+            embed.imgs.clear();
+            embed.imgs.push(img);
+        }
+    }
+
+    let video = match has_type(primary, PRIMARY_TYPES[0]) {
+        true => Some(primary),
+        false => graph.first(primary, "video").or_else(|| graph.find(PRIMARY_TYPES[0])),
+    };
+
+    if let Some(video) = video {
+        if EmbedMedia::is_empty(&embed.video) {
+            let mut media = EmbedMedia::default();
+
+            // prefer the raw video file, otherwise the embeddable player
+            if let Some(url) = video.get("contentUrl").and_then(text) {
+                media.url = url.into();
+                media.mime =
+                    video.get("encodingFormat").and_then(text).filter(|m| m.contains('/')).map(From::from);
+            } else if let Some(url) = video.get("embedUrl").and_then(text) {
+                media.url = url.into();
+                media.mime = Some(From::from("text/html"));
+            }
+
+            if !media.url.is_empty() {
+                media.width = dimension(video.get("width"));
+                media.height = dimension(video.get("height"));
+
+                embed.video = Some(Box::new(media));
+            }
+        }
+
+        if embed.thumb.is_none() {
+            embed.thumb = graph
+                .first(video, "thumbnailUrl")
+                .or_else(|| graph.first(video, "thumbnail"))
+                .and_then(media)
+                .map(Box::new);
+        }
+
+        if let Some(duration) = video.get("duration").and_then(text) {
+            super::utils::add_duration_field(embed, duration);
+        }
+    }
+
+    if embed.thumb.is_none() {
+        embed.thumb = graph.first(primary, "thumbnailUrl").and_then(media).map(Box::new);
+    }
+
+    ["datePublished", "uploadDate"]
+        .iter()
+        .find_map(|key| primary.get(*key).and_then(text).and_then(Timestamp::parse))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_ld() {
+        let fixture = r#"{
+            "@context": "https://schema.org",
+            "@graph": [
+                {
+                    "@type": "NewsArticle",
+                    "headline": "Headline",
+                    "description": "Description",
+                    "image": {"@id": "https://example.com/#primaryimage"},
+                    "author": [{"@type": "Person", "name": "Author", "url": "https://example.com/author"}],
+                    "publisher": {"@id": "https://example.com/#org"},
+                    "datePublished": "2024-01-02T03:04:05Z"
+                },
+                {
+                    "@type": "ImageObject",
+                    "@id": "https://example.com/#primaryimage",
+                    "url": "https://example.com/image.png",
+                    "width": 1200,
+                    "height": "630"
+                },
+                {
+                    "@type": "Organization",
+                    "@id": "https://example.com/#org",
+                    "name": "Example",
+                    "logo": {"@type": "ImageObject", "url": "https://example.com/logo.png"}
+                }
+            ]
+        }"#;
+
+        let mut embed = EmbedV1::default();
+
+        embed.title = Some(From::from("Meta Title"));
+
+        let published = parse_json_ld_to_embed(&mut embed, &[fixture]);
+
+        assert!(published.is_some());
+        assert_eq!(embed.title.as_deref(), Some("Meta Title"));
+        assert_eq!(embed.description.as_deref(), Some("Description"));
+        assert_eq!(embed.author.as_ref().map(|a| a.name.as_str()), Some("Author"));
+        assert_eq!(embed.provider.name.as_deref(), Some("Example"));
+        assert_eq!(
+            embed.provider.icon.as_ref().map(|i| &*i.url),
+            Some("https://example.com/logo.png")
+        );
+        assert_eq!(embed.imgs[0].width, Some(1200));
+        assert_eq!(embed.imgs[0].height, Some(630));
+    }
+}
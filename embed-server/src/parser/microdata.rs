@@ -0,0 +1,283 @@
+//! Microdata properties, mapped according to the type of their enclosing `itemscope`
+//! and how that scope relates to its parent, e.g. the `thumbnail` of a `VideoObject`.
+
+use embed::timestamp::Timestamp;
+use embed::*;
+
+use super::embed::ExtraFields;
+use super::html::{Header, Meta, Scope};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ItemType {
+    Video,
+    Image,
+    Person,
+    Organization,
+    Article,
+}
+
+impl ItemType {
+    fn of(scope: &Scope) -> Option<ItemType> {
+        Some(match scope.type_name()? {
+            "VideoObject" | "Movie" | "Clip" => ItemType::Video,
+            "ImageObject" => ItemType::Image,
+            "Person" => ItemType::Person,
+            "Organization" | "Corporation" | "NewsMediaOrganization" => ItemType::Organization,
+            "Article" | "NewsArticle" | "BlogPosting" | "TechArticle" | "ScholarlyArticle" => {
+                ItemType::Article
+            }
+            _ => return None,
+        })
+    }
+}
+
+fn get_scope<'s, 'a>(headers: &'s [Header<'a>], idx: Option<usize>) -> Option<&'s Scope<'a>> {
+    match headers.get(idx?) {
+        Some(Header::Scope(scope)) => Some(scope),
+        _ => None,
+    }
+}
+
+/// Set the value only if nothing else has
+fn set_default<T: for<'c> From<&'c str>>(value: &mut Option<T>, content: &str) {
+    if value.is_none() {
+        *value = Some(T::from(content));
+    }
+}
+
+/// Apply an `itemprop` within an `itemscope`, returning false if it wasn't used
+pub fn parse_item_prop(
+    embed: &mut EmbedV1,
+    extra: &mut ExtraFields,
+    headers: &[Header],
+    meta: &Meta,
+) -> bool {
+    let Some(scope) = get_scope(headers, meta.scope) else {
+        return false;
+    };
+
+    // YouTube uses `itemid` rather than `itemprop` for the author
+    let relation = scope.prop.as_deref().or(scope.id.as_deref());
+    let parent = get_scope(headers, scope.parent).and_then(ItemType::of);
+
+    let ty = match (ItemType::of(scope), relation) {
+        (None, Some("author" | "creator")) => Some(ItemType::Person),
+        (ty, _) => ty,
+    };
+
+    let content = meta.content.as_ref();
+
+    macro_rules! get {
+        ($e:ident $(. $rest:ident)*) => {
+            embed.$e $(.$rest)*.get_or_insert_with(Default::default)
+        };
+    }
+
+    match (ty, &*meta.property) {
+        (Some(ItemType::Person | ItemType::Organization), prop) if relation == Some("publisher") => {
+            match prop {
+                "name" => set_default(&mut embed.provider.name, content),
+                "url" => set_default(&mut embed.provider.url, content),
+                _ => {}
+            }
+        }
+
+        (Some(ty @ (ItemType::Person | ItemType::Organization)), prop) => {
+            if ty == ItemType::Organization && !matches!(relation, Some("author" | "creator")) {
+                return true;
+            }
+
+            match prop {
+                "name" => get!(author).name = content.into(),
+                "url" => get!(author).url = Some(content.into()),
+                _ => {}
+            }
+        }
+
+        (Some(ItemType::Image), prop) => {
+            let media = match relation {
+                Some("logo") if parent == Some(ItemType::Organization) => &mut **get!(provider.icon),
+                Some("thumbnail") => &mut **get!(thumb),
+                _ => {
+                    if embed.imgs.is_empty() {
+                        embed.imgs.push(EmbedMedia::default());
+                    }
+
+                    embed.imgs.first_mut().unwrap()
+                }
+            };
+
+            match prop {
+                "url" | "contentUrl" if media.url.is_empty() => media.url = content.into(),
+                "width" => media.width = media.width.or(content.parse().ok()),
+                "height" => media.height = media.height.or(content.parse().ok()),
+                "caption" => set_default(&mut media.description, content),
+                "encodingFormat" if content.contains('/') => set_default(&mut media.mime, content),
+                _ => {}
+            }
+        }
+
+        (Some(ItemType::Video), prop) => match prop {
+            "name" => set_default(&mut embed.title, content),
+            "description" => {
+                if embed.description.is_none() {
+                    embed.description = Some(crate::util::trim_text(content).into());
+                }
+            }
+            // embeddable player, only if there's nothing better
+            "embedUrl" => {
+                let video = get!(video);
+
+                if video.url.is_empty() {
+                    video.url = content.into();
+                    video.mime = Some(From::from("text/html"));
+                }
+            }
+            // raw video file, preferred over a player
+            "contentUrl" => {
+                let video = get!(video);
+
+                if video.url.is_empty() || video.mime.as_deref() == Some("text/html") {
+                    video.url = content.into();
+                    video.mime = None;
+                }
+            }
+            "width" => {
+                let video = get!(video);
+
+                video.width = video.width.or(content.parse().ok());
+            }
+            "height" => {
+                let video = get!(video);
+
+                video.height = video.height.or(content.parse().ok());
+            }
+            "thumbnailUrl" => {
+                let thumb = get!(thumb);
+
+                if thumb.url.is_empty() {
+                    thumb.url = content.into();
+                }
+            }
+            "duration" => super::utils::add_duration_field(embed, content),
+            "uploadDate" | "datePublished" => {
+                extra.published = extra.published.or(Timestamp::parse(content));
+            }
+            _ => return false,
+        },
+
+        (Some(ItemType::Article), prop) => match prop {
+            "headline" | "name" => set_default(&mut embed.title, content),
+            "description" => {
+                if embed.description.is_none() {
+                    embed.description = Some(crate::util::trim_text(content).into());
+                }
+            }
+            "image" | "thumbnailUrl" if embed.imgs.iter().all(|img| img.url.is_empty()) => {
+                let mut img = EmbedMedia::default();
+
+                img.url = content.into();
+
+                embed.imgs.clear();
+                embed.imgs.push(img);
+            }
+            "author" => {
+                if embed.author.is_none() {
+                    get!(author).name = content.into();
+                }
+            }
+            "datePublished" => {
+                extra.published = extra.published.or(Timestamp::parse(content));
+            }
+            _ => return false,
+        },
+
+        _ => return false,
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_scopes() {
+        let fixture = r#"
+        <div itemscope itemtype="http://schema.org/VideoObject">
+            <meta itemprop="name" content="Video Title">
+            <span itemprop="author" itemscope itemtype="http://schema.org/Person">
+                <link itemprop="url" href="https://example.com/@author">
+                <span itemprop="name">Author</span>
+            </span>
+            <span itemprop="thumbnail" itemscope itemtype="http://schema.org/ImageObject">
+                <link itemprop="url" href="https://example.com/thumb.jpg">
+                <meta itemprop="width" content="1280">
+            </span>
+            <link itemprop="embedUrl" href="https://example.com/embed/video">
+            <meta itemprop="duration" content="PT1M33S">
+            <div><span>unrelated</span></div>
+            <meta itemprop="uploadDate" content="2024-01-02T03:04:05Z">
+        </div>
+        <span itemprop="name">Outside</span>"#;
+
+        let headers = crate::parser::html::parse_meta(fixture, false).0;
+
+        let mut embed = EmbedV1::default();
+        let extra = crate::parser::embed::parse_meta_to_embed(&mut embed, &headers, 4);
+
+        assert_eq!(embed.title.as_deref(), Some("Video Title"));
+        assert!(extra.published.is_some());
+
+        let author = embed.author.as_ref().unwrap();
+        assert_eq!(author.name.as_str(), "Author");
+        assert_eq!(author.url.as_deref(), Some("https://example.com/@author"));
+
+        let thumb = embed.thumb.as_ref().unwrap();
+        assert_eq!(&*thumb.url, "https://example.com/thumb.jpg");
+        assert_eq!(thumb.width, Some(1280));
+
+        assert_eq!(
+            embed.video.as_ref().map(|v| &*v.url),
+            Some("https://example.com/embed/video")
+        );
+        assert_eq!(embed.fields.len(), 1);
+
+        // the last property is outside of any scope
+        assert!(matches!(headers.last(), Some(Header::Meta(meta)) if meta.scope.is_none()));
+    }
+
+    #[test]
+    fn test_body_microdata() {
+        // as parsed for pages without field selectors, which only take structured data from the body
+        let fixture = r#"<html><head>
+            <meta property="og:title" content="Page Title">
+        </head>
+        <body>
+            <meta property="og:image" content="https://example.com/widget.png">
+            <div itemscope itemtype="http://schema.org/VideoObject">
+                <span itemprop="author" itemscope itemtype="http://schema.org/Person">
+                    <link itemprop="url" href="https://example.com/@author">
+                    <link itemprop="name" content="Author">
+                </span>
+                <link itemprop="embedUrl" href="https://example.com/embed/video">
+            </div>
+            <script type="application/ld+json">{"@type":"VideoObject","name":"JSON"}</script>
+        </body></html>"#;
+
+        let headers = crate::parser::html::parse_meta(fixture, true).0;
+
+        let mut embed = EmbedV1::default();
+        crate::parser::embed::parse_meta_to_embed(&mut embed, &headers, 4);
+
+        assert_eq!(embed.title.as_deref(), Some("Page Title"));
+        assert!(embed.imgs.is_empty());
+        assert_eq!(embed.author.as_ref().map(|a| a.name.as_str()), Some("Author"));
+        assert_eq!(
+            embed.video.as_ref().map(|v| &*v.url),
+            Some("https://example.com/embed/video")
+        );
+        assert!(headers.iter().any(|h| matches!(h, Header::JsonLd(_))));
+    }
+}
@@ -1,9 +1,16 @@
+pub mod charset;
 pub mod embed;
 pub mod feed;
 pub mod html;
+pub mod iframe;
+pub mod jsonld;
+pub mod microdata;
 pub mod oembed;
 pub mod patterns;
+pub mod pdf;
 pub mod quirks;
+pub mod readability;
+pub mod tokenizer;
 pub mod utils;
 
 #[inline]
@@ -11,31 +18,6 @@ fn trim_quotes(s: &str) -> &str {
     s.trim_matches(|c: char| ['"', '\'', '“', '”'].contains(&c) || c.is_whitespace())
 }
 
-#[rustfmt::skip]
-pub mod regexes {
-    use regex::Regex;
-    use std::sync::LazyLock;
-
-    pub static ATTRIBUTE_RE: LazyLock<Regex> = LazyLock::new(|| {
-        Regex::new(r#"(?x)
-            [a-zA-Z_][0-9a-zA-Z\-_]+\s*=\s*(
-            ("(?:\\"|[^"])*[^\\]")| # name="value"
-            ('(?:\\'|[^'])*[^\\]')| # name='value'
-            ([^'"](?:\\\s|[^\s>]*)) # name=value or name=value>
-        )"#).unwrap()
-    });
-
-    pub static META_TAGS: LazyLock<Regex> = LazyLock::new(|| {
-        Regex::new(r"(?x)
-            <(?i)( # NOTE: Tags are case-insensitive
-            meta\x20|                   # Regular meta tags
-            title[^>]*>|                # <title> element, skipping over attributes
-            link\x20|                   # link elements
-            ((div|span)[^>]+itemscope)  # itemscopes
-        )").unwrap()
-    });
-}
-
 /// We can't embed infinite text, so this attempts to trim it below `max_len` without abrubtly
 /// cutting off. It will find punctuation nearest to the limit and trim to there, or
 pub fn trim_text(mut text: &str, max_len: usize) -> &str {
@@ -45,7 +27,14 @@ pub fn trim_text(mut text: &str, max_len: usize) -> &str {
         return text;
     }
 
-    text = &text[..max_len];
+    // back off to the start of the character the limit falls within
+    let mut end = max_len;
+
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    text = &text[..end];
 
     // try to find punctuation
     for (idx, char) in text.char_indices().rev() {
@@ -0,0 +1,421 @@
+//! Minimal PDF metadata scanner, for the document info dictionary, XMP metadata and page count.
+//!
+//! Only what's needed for an embed is found, by scanning for the relevant objects rather than
+//! following the cross-reference table, so it also works on the first and last parts of a
+//! document fetched with range requests. Compressed object streams are not decoded,
+//! so anything stored only in those is missed.
+
+use std::borrow::Cow;
+
+use embed::timestamp::Timestamp;
+use memchr::memmem;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PdfInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub created: Option<Timestamp>,
+    pub pages: Option<u32>,
+}
+
+/// Scan the document for its metadata, preferring XMP over the info dictionary
+pub fn parse_pdf(data: &[u8]) -> Option<PdfInfo> {
+    if !data.starts_with(b"%PDF-") {
+        return None;
+    }
+
+    let mut info = PdfInfo::default();
+
+    if let Some(xmp) = find_xmp(data) {
+        info.title = xmp_value(xmp, "dc:title");
+        info.author = xmp_value(xmp, "dc:creator");
+        info.subject = xmp_value(xmp, "dc:description");
+        info.created = xmp_value(xmp, "xmp:CreateDate").and_then(|date| Timestamp::parse(&date));
+    }
+
+    if let Some(dict) = find_info_dict(data) {
+        let value = |key: &str| dict_string(data, dict, key).filter(|s| !s.is_empty());
+
+        info.title = info.title.or_else(|| value("/Title"));
+        info.author = info.author.or_else(|| value("/Author"));
+        info.subject = info.subject.or_else(|| value("/Subject"));
+        info.created = info.created.or_else(|| value("/CreationDate").as_deref().and_then(parse_date));
+    }
+
+    info.pages = page_count(data);
+
+    Some(info)
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(
+        b,
+        b' ' | b'\t'
+            | b'\r'
+            | b'\n'
+            | b'\x0C'
+            | b'\0'
+            | b'('
+            | b')'
+            | b'<'
+            | b'>'
+            | b'['
+            | b']'
+            | b'/'
+            | b'%'
+    )
+}
+
+fn skip_ws(data: &[u8], mut pos: usize) -> usize {
+    while matches!(
+        data.get(pos),
+        Some(b' ' | b'\t' | b'\r' | b'\n' | b'\x0C' | b'\0')
+    ) {
+        pos += 1;
+    }
+
+    pos
+}
+
+/// Parse an integer at the given position, returning it and the position after it
+fn parse_int(data: &[u8], pos: usize) -> Option<(u32, usize)> {
+    let len = data[pos..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+    let n = std::str::from_utf8(&data[pos..(pos + len)]).ok()?.parse().ok()?;
+
+    Some((n, pos + len))
+}
+
+/// Parse an indirect reference like `12 0 R`
+fn parse_ref(data: &[u8], pos: usize) -> Option<(u32, u32)> {
+    let (num, pos) = parse_int(data, skip_ws(data, pos))?;
+    let (gen, pos) = parse_int(data, skip_ws(data, pos))?;
+
+    (data.get(skip_ws(data, pos)) == Some(&b'R')).then_some((num, gen))
+}
+
+/// Find the start of the body of the last definition of `num gen obj`, as later updates take priority
+fn find_object(data: &[u8], num: u32, gen: u32) -> Option<usize> {
+    let pattern = format!("{num} {gen} obj");
+
+    memmem::find_iter(data, pattern.as_bytes())
+        .filter(|&pos| pos == 0 || !data[pos - 1].is_ascii_digit())
+        .last()
+        .map(|pos| pos + pattern.len())
+}
+
+/// Find the range of the dictionary starting at or after `pos`, including nested dictionaries
+fn find_dict(data: &[u8], pos: usize) -> Option<(usize, usize)> {
+    let start = pos + memmem::find(&data[pos..], b"<<")?;
+
+    let mut depth = 0;
+    let mut i = start;
+
+    while i + 1 < data.len() {
+        match &data[i..(i + 2)] {
+            b"<<" => {
+                depth += 1;
+                i += 2;
+            }
+            b">>" => {
+                depth -= 1;
+                i += 2;
+
+                if depth == 0 {
+                    return Some((start, i));
+                }
+            }
+            [b'(', _] => i = parse_literal(data, i)?.1,
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+fn find_info_dict(data: &[u8]) -> Option<(usize, usize)> {
+    // the trailer, or the cross-reference stream dictionary, points to the info dictionary
+    let key = memmem::rfind_iter(data, b"/Info")
+        .find(|&pos| !matches!(data.get(pos + 5), Some(&b) if !is_delimiter(b)))?;
+
+    let (num, gen) = parse_ref(data, key + 5)?;
+
+    find_dict(data, find_object(data, num, gen)?)
+}
+
+/// Find a string value in the dictionary, resolving it if it's an indirect reference
+fn dict_string(data: &[u8], (start, end): (usize, usize), key: &str) -> Option<String> {
+    let dict = &data[start..end];
+
+    let pos = memmem::find_iter(dict, key.as_bytes())
+        .find(|&pos| dict.get(pos + key.len()).is_some_and(|&b| is_delimiter(b)))?;
+
+    let mut pos = skip_ws(data, start + pos + key.len());
+
+    if data.get(pos)?.is_ascii_digit() {
+        let (num, gen) = parse_ref(data, pos)?;
+
+        pos = skip_ws(data, find_object(data, num, gen)?);
+    }
+
+    let bytes = match data.get(pos..(pos + 2))? {
+        [b'<', b] if *b != b'<' => parse_hex(data, pos)?,
+        [b'(', _] => parse_literal(data, pos)?.0,
+        _ => return None,
+    };
+
+    Some(decode_text(&bytes).trim().to_owned())
+}
+
+/// Parse a literal string like `(Hello \(World\))`, returning its bytes and the position after it
+fn parse_literal(data: &[u8], pos: usize) -> Option<(Vec<u8>, usize)> {
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut i = pos;
+
+    loop {
+        let b = *data.get(i)?;
+        i += 1;
+
+        match b {
+            b'(' => {
+                if depth > 0 {
+                    out.push(b);
+                }
+
+                depth += 1;
+            }
+            b')' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some((out, i));
+                }
+
+                out.push(b);
+            }
+            b'\\' => {
+                let e = *data.get(i)?;
+                i += 1;
+
+                match e {
+                    b'n' => out.push(b'\n'),
+                    b'r' => out.push(b'\r'),
+                    b't' => out.push(b'\t'),
+                    b'b' => out.push(b'\x08'),
+                    b'f' => out.push(b'\x0C'),
+                    // line continuation
+                    b'\r' => i += (data.get(i) == Some(&b'\n')) as usize,
+                    b'\n' => {}
+                    b'0'..=b'7' => {
+                        let mut n = (e - b'0') as u32;
+
+                        for _ in 0..2 {
+                            match data.get(i) {
+                                Some(&d @ b'0'..=b'7') => {
+                                    n = n * 8 + (d - b'0') as u32;
+                                    i += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+
+                        out.push(n as u8);
+                    }
+                    _ => out.push(e),
+                }
+            }
+            _ => out.push(b),
+        }
+    }
+}
+
+/// Parse a hex string like `<48656C6C6F>`
+fn parse_hex(data: &[u8], pos: usize) -> Option<Vec<u8>> {
+    let end = pos + memchr::memchr(b'>', &data[pos..])?;
+
+    let digits: Vec<u8> = data[(pos + 1)..end].iter().copied().filter(u8::is_ascii_hexdigit).collect();
+
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| {
+                let hex = |d: u8| (d as char).to_digit(16).unwrap_or(0) as u8;
+
+                // a missing final digit is assumed to be 0
+                (hex(pair[0]) << 4) | pair.get(1).map_or(0, |&d| hex(d))
+            })
+            .collect(),
+    )
+}
+
+/// Text strings are either UTF-16BE with a byte-order mark, UTF-8 with one, or PDFDocEncoding,
+/// which is close enough to Latin-1 for the characters that matter
+fn decode_text(bytes: &[u8]) -> Cow<'_, str> {
+    if let Some(utf16) = bytes.strip_prefix(b"\xFE\xFF") {
+        let units = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+
+        return Cow::Owned(char::decode_utf16(units).map(|c| c.unwrap_or('\u{FFFD}')).collect());
+    }
+
+    if let Some(utf8) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return String::from_utf8_lossy(utf8);
+    }
+
+    Cow::Owned(bytes.iter().map(|&b| b as char).collect())
+}
+
+/// Parse a date like `D:20240103120000+01'00'`, where everything after the year is optional
+pub fn parse_date(date: &str) -> Option<Timestamp> {
+    let date = date.trim();
+    let date = date.strip_prefix("D:").unwrap_or(date);
+
+    let digits = date.bytes().take_while(u8::is_ascii_digit).count();
+
+    if digits < 4 {
+        return None;
+    }
+
+    let part = |start: usize, default: &'static str| {
+        date.get(start..(start + 2)).filter(|_| start + 2 <= digits).unwrap_or(default)
+    };
+
+    let offset = match date[digits..].as_bytes() {
+        [sign @ (b'+' | b'-'), rest @ ..] => {
+            let rest: String = rest.iter().filter(|b| b.is_ascii_digit()).map(|&b| b as char).collect();
+
+            format!(
+                "{}{}:{}",
+                *sign as char,
+                rest.get(0..2).unwrap_or("00"),
+                rest.get(2..4).unwrap_or("00")
+            )
+        }
+        _ => "Z".to_owned(),
+    };
+
+    Timestamp::parse(&format!(
+        "{}-{}-{}T{}:{}:{}{offset}",
+        &date[..4],
+        part(4, "01"),
+        part(6, "01"),
+        part(8, "00"),
+        part(10, "00"),
+        part(12, "00"),
+    ))
+}
+
+/// The page tree root has the total page count, so it's the largest `/Count` of any node with `/Kids`
+fn page_count(data: &[u8]) -> Option<u32> {
+    memmem::find_iter(data, b"/Count")
+        .filter_map(|pos| {
+            // innermost dictionary around it, which for page tree nodes contains no other dictionaries
+            let start = memmem::rfind(&data[..pos], b"<<")?;
+            let end = pos + memmem::find(&data[pos..], b">>")?;
+
+            memmem::find(&data[start..end], b"/Kids")?;
+
+            parse_int(data, skip_ws(data, pos + 6)).map(|(count, _)| count)
+        })
+        .max()
+}
+
+fn find_xmp(data: &[u8]) -> Option<&[u8]> {
+    let start = memmem::find(data, b"<x:xmpmeta").or_else(|| memmem::find(data, b"<rdf:RDF"))?;
+    let end = start + memmem::find(&data[start..], b"</rdf:RDF>")?;
+
+    Some(&data[start..end])
+}
+
+/// Get a simple XMP property, either as an attribute, or as an element with an optional `rdf:Alt`, `rdf:Seq` or `rdf:Bag`
+fn xmp_value(xmp: &[u8], name: &str) -> Option<String> {
+    let xmp = std::str::from_utf8(xmp).ok()?;
+
+    let value = match xmp.find(&format!("{name}=\"")) {
+        Some(attr) => {
+            let value = &xmp[(attr + name.len() + 2)..];
+
+            &value[..value.find('"')?]
+        }
+        None => {
+            let start = xmp.find(&format!("<{name}"))?;
+            let end = start + xmp[start..].find(&format!("</{name}>"))?;
+
+            let mut element = &xmp[start..end];
+
+            if let Some(li) = element.find("<rdf:li") {
+                element = &element[li..];
+            }
+
+            let text = &element[(element.find('>')? + 1)..];
+
+            &text[..text.find('<').unwrap_or(text.len())]
+        }
+    };
+
+    let value = html_escape::decode_html_entities(value.trim());
+
+    (!value.is_empty()).then(|| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pdf() {
+        let fixture = br#"%PDF-1.4
+1 0 obj
+<< /Type /Catalog /Pages 2 0 R /Outlines 8 0 R >>
+endobj
+2 0 obj
+<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 12 >>
+endobj
+3 0 obj
+<< /Type /Pages /Parent 2 0 R /Kids [5 0 R] /Count 4 >>
+endobj
+8 0 obj
+<< /Type /Outlines /First 9 0 R /Count 30 >>
+endobj
+6 0 obj
+<< /Title (Notes on \(Linear\) Algebra) /Author <FEFF004A0061006E006500200044006F0065> /Subject 7 0 R
+   /CreationDate (D:20240103120000+01'00') /Producer (Something) >>
+endobj
+7 0 obj
+(Chapter 1\056)
+endobj
+trailer
+<< /Size 9 /Root 1 0 R /Info 6 0 R >>
+%%EOF"#;
+
+        let info = parse_pdf(fixture).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("Notes on (Linear) Algebra"));
+        assert_eq!(info.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(info.subject.as_deref(), Some("Chapter 1."));
+        assert_eq!(info.created, Timestamp::parse("2024-01-03T11:00:00Z"));
+        assert_eq!(info.pages, Some(12));
+
+        assert_eq!(parse_date("D:2023"), Timestamp::parse("2023-01-01T00:00:00Z"));
+        assert_eq!(parse_pdf(b"<html>"), None);
+    }
+
+    #[test]
+    fn test_xmp() {
+        let xmp = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF>
+            <rdf:Description xmp:CreateDate="2024-01-03T12:00:00Z">
+                <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Research &amp; Results</rdf:li></rdf:Alt></dc:title>
+                <dc:creator><rdf:Seq><rdf:li>Jane Doe</rdf:li><rdf:li>John Doe</rdf:li></rdf:Seq></dc:creator>
+            </rdf:Description>
+        </rdf:RDF></x:xmpmeta>"#;
+
+        assert_eq!(xmp_value(xmp, "dc:title").as_deref(), Some("Research & Results"));
+        assert_eq!(xmp_value(xmp, "dc:creator").as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            xmp_value(xmp, "xmp:CreateDate").as_deref(),
+            Some("2024-01-03T12:00:00Z")
+        );
+        assert_eq!(xmp_value(xmp, "dc:description"), None);
+    }
+}
@@ -0,0 +1,394 @@
+//! Readability-style fallback for pages without a description or image in their metadata.
+//!
+//! Paragraphs are scored by length and punctuation, and their scores added to the enclosing
+//! containers, so the container of the main content ends up with the highest score. Its first
+//! meaningful paragraph becomes the description, and its first reasonably large image the lead image.
+//!
+//! Navigation, headers, footers, sidebars and the like are skipped entirely.
+
+use std::borrow::Cow;
+
+use embed::v1::{EmbedMedia, EmbedV1};
+
+use super::tokenizer::{Tag, Token, Tokenizer};
+
+/// Maximum length of the fallback description
+const MAX_DESCRIPTION_LEN: usize = 400;
+
+/// Paragraphs shorter than this are usually captions, bylines or buttons
+const MIN_PARAGRAPH_LEN: usize = 60;
+
+/// Images smaller than this in either dimension are likely icons or tracking pixels
+const MIN_IMAGE_SIZE: u32 = 100;
+
+/// Elements that can contain the main content
+const CONTAINERS: &[&str] = &["article", "main", "section", "div", "td", "body"];
+
+/// Elements whose contents are never part of the main content
+const SKIPPED: &[&str] = &[
+    "nav",
+    "header",
+    "footer",
+    "aside",
+    "form",
+    "script",
+    "style",
+    "noscript",
+    "template",
+    "svg",
+    "button",
+    "figcaption",
+];
+
+/// Class or id names of containers that are almost never the main content
+const NEGATIVE: &[&str] = &[
+    "comment",
+    "sidebar",
+    "footer",
+    "masthead",
+    "menu",
+    "share",
+    "social",
+    "related",
+    "cookie",
+    "banner",
+    "promo",
+    "advert",
+    "sponsor",
+    "newsletter",
+    "popup",
+    "modal",
+];
+
+/// Class or id names of containers that are likely the main content
+const POSITIVE: &[&str] = &[
+    "article", "content", "entry", "post", "story", "main", "body", "text", "blog",
+];
+
+/// Returns true if the embed has no description or no image to show
+pub fn is_sparse(embed: &EmbedV1) -> bool {
+    embed.description.is_none() || !has_image(embed)
+}
+
+fn has_image(embed: &EmbedV1) -> bool {
+    !embed.imgs.is_empty() || embed.thumb.is_some() || embed.video.is_some() || embed.obj.is_some()
+}
+
+/// Fill in any missing description or image from the main content of the page body
+pub fn apply_fallback(embed: &mut EmbedV1, html: &str) {
+    let Some(content) = find_main_content(html) else {
+        return;
+    };
+
+    if embed.description.is_none() {
+        if let Some(paragraph) = content.paragraph {
+            embed.description = Some(super::trim_text(&paragraph, MAX_DESCRIPTION_LEN).into());
+        }
+    }
+
+    if !has_image(embed) {
+        if let Some(img) = content.image {
+            embed.imgs.push(img);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Container {
+    score: f32,
+    /// First meaningful paragraph within the container, including nested containers
+    paragraph: Option<String>,
+    /// First large enough image within the container, including nested containers
+    image: Option<EmbedMedia>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Container(usize),
+    Skipped,
+    Paragraph,
+}
+
+struct Open<'a> {
+    name: &'a str,
+    kind: Kind,
+}
+
+struct Scorer<'a> {
+    containers: Vec<Container>,
+    stack: Vec<Open<'a>>,
+    /// Text of the current paragraph, with whitespace collapsed
+    text: String,
+}
+
+fn matches_any(tag: &Tag, names: &[&str]) -> bool {
+    tag.attrs().any(|(attr, value)| {
+        (attr.eq_ignore_ascii_case("class") || attr.eq_ignore_ascii_case("id")) && {
+            let value = value.to_ascii_lowercase();
+
+            names.iter().any(|name| value.contains(name))
+        }
+    })
+}
+
+impl<'a> Scorer<'a> {
+    fn skipping(&self) -> bool {
+        self.stack.iter().any(|open| open.kind == Kind::Skipped)
+    }
+
+    fn in_paragraph(&self) -> bool {
+        matches!(self.stack.last(), Some(open) if open.kind == Kind::Paragraph)
+    }
+
+    /// Indices of the containers enclosing the current position, innermost first
+    fn containers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.stack.iter().rev().filter_map(|open| match open.kind {
+            Kind::Container(idx) => Some(idx),
+            _ => None,
+        })
+    }
+
+    fn start_tag(&mut self, tag: Tag<'a>) {
+        let is = |names: &[&str]| names.iter().any(|name| name.eq_ignore_ascii_case(tag.name));
+
+        if is(&["img"]) {
+            if !self.skipping() {
+                self.image(&tag);
+            }
+
+            return;
+        }
+
+        let kind = if is(SKIPPED) {
+            Kind::Skipped
+        } else if is(&["p", "pre", "blockquote"]) {
+            Kind::Paragraph
+        } else if is(CONTAINERS) {
+            if matches_any(&tag, NEGATIVE) && !is(&["body"]) {
+                Kind::Skipped
+            } else {
+                let score = if matches_any(&tag, POSITIVE) { 25.0 } else { 0.0 };
+
+                self.containers.push(Container {
+                    score,
+                    ..Container::default()
+                });
+
+                Kind::Container(self.containers.len() - 1)
+            }
+        } else {
+            return;
+        };
+
+        if tag.self_closing {
+            return;
+        }
+
+        // paragraphs can't contain blocks, so they're implicitly closed
+        if kind != Kind::Skipped && self.in_paragraph() {
+            self.close(self.stack.len() - 1);
+        }
+
+        self.stack.push(Open { name: tag.name, kind });
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        if let Some(idx) = self.stack.iter().rposition(|open| open.name.eq_ignore_ascii_case(name)) {
+            self.close(idx);
+        }
+    }
+
+    /// Close everything from the given stack index onwards
+    fn close(&mut self, idx: usize) {
+        while self.stack.len() > idx {
+            if let Some(Open {
+                kind: Kind::Paragraph,
+                ..
+            }) = self.stack.pop()
+            {
+                self.paragraph();
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if !self.in_paragraph() || self.skipping() {
+            return;
+        }
+
+        let text = html_escape::decode_html_entities(text);
+
+        for word in text.split_whitespace() {
+            if !self.text.is_empty() {
+                self.text.push(' ');
+            }
+
+            self.text.push_str(word);
+        }
+    }
+
+    fn paragraph(&mut self) {
+        let text = std::mem::take(&mut self.text);
+
+        if self.skipping() || text.len() < MIN_PARAGRAPH_LEN {
+            return;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f32 + (text.len() / 100).min(3) as f32;
+
+        let containers = self.containers().collect::<Vec<_>>();
+
+        // parent gets the full score, grandparent half of it
+        for (&idx, weight) in containers.iter().zip([1.0, 0.5]) {
+            self.containers[idx].score += score * weight;
+        }
+
+        for idx in containers {
+            let container = &mut self.containers[idx];
+
+            if container.paragraph.is_none() {
+                container.paragraph = Some(text.clone());
+            }
+        }
+    }
+
+    fn image(&mut self, tag: &Tag) {
+        let mut media = EmbedMedia::default();
+        let mut src: Option<Cow<str>> = None;
+
+        for (attr, value) in tag.attrs() {
+            match attr {
+                // lazy-loaded images often have a placeholder in `src`
+                "src" if src.is_none() => src = Some(value),
+                "data-src" | "data-lazy-src" | "data-original" => src = Some(value),
+                "alt" if !value.trim().is_empty() => media.description = Some(value.trim().into()),
+                "width" => media.width = value.trim_end_matches("px").parse().ok(),
+                "height" => media.height = value.trim_end_matches("px").parse().ok(),
+                _ => {}
+            }
+        }
+
+        let Some(src) = src else { return };
+
+        if src.starts_with("data:") || src.trim().is_empty() {
+            return;
+        }
+
+        if media.width.is_some_and(|w| w < MIN_IMAGE_SIZE as _)
+            || media.height.is_some_and(|h| h < MIN_IMAGE_SIZE as _)
+        {
+            return;
+        }
+
+        let lower = src.to_ascii_lowercase();
+
+        if ["logo", "icon", "avatar", "sprite", "pixel", "badge"].iter().any(|s| lower.contains(s)) {
+            return;
+        }
+
+        media.url = src.trim().into();
+
+        for idx in self.containers().collect::<Vec<_>>() {
+            let container = &mut self.containers[idx];
+
+            if container.image.is_none() {
+                container.image = Some(media.clone());
+            }
+        }
+    }
+}
+
+/// The main content found by [`find_main_content`]
+struct MainContent {
+    paragraph: Option<String>,
+    image: Option<EmbedMedia>,
+}
+
+fn find_main_content(html: &str) -> Option<MainContent> {
+    let mut scorer = Scorer {
+        containers: Vec::new(),
+        stack: Vec::new(),
+        text: String::new(),
+    };
+
+    for token in Tokenizer::new(html) {
+        match token {
+            Token::StartTag(tag) => scorer.start_tag(tag),
+            Token::EndTag(name) => scorer.end_tag(name),
+            Token::Text(text) => scorer.text(text),
+        }
+    }
+
+    scorer.close(0);
+
+    let best = scorer
+        .containers
+        .into_iter()
+        .filter(|c| c.score > 0.0 && c.paragraph.is_some())
+        .max_by(|a, b| a.score.total_cmp(&b.score))?;
+
+    Some(MainContent {
+        paragraph: best.paragraph,
+        image: best.image,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main_content() {
+        let fixture = r#"<html><body>
+            <header><img src="/logo.png"><p>Welcome to the best blog on the whole internet, or so they say.</p></header>
+            <nav><p>Home, About, Archive, Contact, and a few more links that nobody ever clicks on.</p></nav>
+            <div class="post-content">
+                <h1>Title</h1>
+                <p>Short intro.</p>
+                <img src="/pixel.gif" width="1" height="1">
+                <img data-src="/images/lead.jpg" src="data:image/gif;base64,R0lGOD" alt="The lead image">
+                <p>This is the first real paragraph of the article, with   enough text &amp; some commas, clauses, and so on.
+                <p>And a second paragraph, which should not be used, even though it also has plenty of text in it.</p>
+            </div>
+            <div class="comments"><p>Great post, thanks for sharing, I learned a lot, keep it up, more please!</p></div>
+            <footer><p>Copyright, all rights reserved, do not copy, this means you, seriously, please don't.</p></footer>
+        </body></html>"#;
+
+        let mut embed = EmbedV1::default();
+        embed.title = Some("Title".into());
+
+        assert!(is_sparse(&embed));
+
+        apply_fallback(&mut embed, fixture);
+
+        assert_eq!(
+            embed.description.as_deref(),
+            Some("This is the first real paragraph of the article, with enough text & some commas, clauses, and so on.")
+        );
+
+        assert_eq!(embed.imgs.len(), 1);
+        assert_eq!(&*embed.imgs[0].url, "/images/lead.jpg");
+        assert_eq!(embed.imgs[0].description.as_deref(), Some("The lead image"));
+
+        // nothing to do when the metadata was enough
+        let mut embed = EmbedV1::default();
+        embed.description = Some("From meta".into());
+        embed.imgs.push(EmbedMedia::default());
+
+        assert!(!is_sparse(&embed));
+    }
+
+    #[test]
+    fn test_multibyte_paragraph() {
+        // 3-byte characters, so the limit falls within one
+        let paragraph = "日本語の文章".repeat(30);
+        let fixture = format!("<html><body><article><p>{paragraph}</p></article></body></html>");
+
+        let mut embed = EmbedV1::default();
+        apply_fallback(&mut embed, &fixture);
+
+        let description = embed.description.unwrap();
+        assert!(description.len() <= MAX_DESCRIPTION_LEN);
+        assert!(paragraph.starts_with(&*description));
+    }
+}
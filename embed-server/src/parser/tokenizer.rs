@@ -0,0 +1,351 @@
+//! Minimal streaming HTML tokenizer, just enough to find tags and their attributes in a single pass.
+//!
+//! Unlike a full HTML parser, nothing is allocated and no tree is built. Comments, doctypes and
+//! processing instructions are skipped, quoted attribute values may contain `>`, and the contents
+//! of raw text elements like `<script>` are returned as a single text token without looking for tags.
+//!
+//! NOTE: This file must not depend on the rest of the crate, as it's also included by the benchmarks.
+
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<'a> {
+    StartTag(Tag<'a>),
+    /// Name of the closing tag
+    EndTag(&'a str),
+    /// Text between tags, with HTML entities still encoded
+    Text(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag<'a> {
+    pub name: &'a str,
+    /// Raw attributes of the tag, see [`Tag::attrs`]
+    pub raw_attrs: &'a str,
+    /// `<tag />`
+    pub self_closing: bool,
+}
+
+impl<'a> Tag<'a> {
+    /// Iterate over the `(name, value)` pairs of the attributes, with entities decoded
+    pub fn attrs(&self) -> Attributes<'a> {
+        Attributes {
+            input: self.raw_attrs,
+        }
+    }
+}
+
+/// Elements whose contents are text, not markup
+const RAW_TEXT: &[&str] = &[
+    "script", "style", "title", "textarea", "xmp", "iframe", "noembed", "noframes",
+];
+
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+    /// Set after the start tag of a raw text element, until its contents are returned
+    raw_text: Option<&'a str>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer {
+            input,
+            pos: 0,
+            raw_text: None,
+        }
+    }
+
+    /// Byte offset of the next token
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        self.input.as_bytes()
+    }
+
+    /// Find `</name` from the current position, ignoring case
+    fn find_end_tag(&self, name: &str) -> Option<usize> {
+        let bytes = self.bytes();
+        let mut pos = self.pos;
+
+        loop {
+            pos += memchr::memmem::find(&bytes[pos..], b"</")?;
+
+            let name_end = pos + 2 + name.len();
+
+            if let Some(tag) = bytes.get((pos + 2)..name_end) {
+                if tag.eq_ignore_ascii_case(name.as_bytes())
+                    && matches!(
+                        bytes.get(name_end),
+                        None | Some(b'>' | b'/' | b' ' | b'\t' | b'\r' | b'\n' | b'\x0C')
+                    )
+                {
+                    return Some(pos);
+                }
+            }
+
+            pos += 2;
+        }
+    }
+
+    /// Find the `>` ending a tag, skipping over any quoted attribute values
+    fn find_tag_end(&self, start: usize) -> Option<usize> {
+        let bytes = self.bytes();
+        let mut pos = start;
+        let mut after_eq = false;
+
+        while let Some(&b) = bytes.get(pos) {
+            match b {
+                b'>' => return Some(pos),
+                b'"' | b'\'' if after_eq => {
+                    pos += 1 + memchr::memchr(b, &bytes[(pos + 1)..])?;
+                    after_eq = false;
+                }
+                b'=' => after_eq = true,
+                b' ' | b'\t' | b'\r' | b'\n' | b'\x0C' => {}
+                _ => after_eq = false,
+            }
+
+            pos += 1;
+        }
+
+        None
+    }
+
+    /// Find the next `<` that starts a tag, comment or similar, skipping over any stray `<` in text
+    fn find_markup(&self, from: usize) -> Option<usize> {
+        let bytes = self.bytes();
+
+        memchr::memchr_iter(b'<', &bytes[from..]).map(|lt| lt + from).find(|&lt| match bytes.get(lt + 1) {
+            Some(b'!' | b'?') => true,
+            Some(b'/') => bytes.get(lt + 2).is_some_and(u8::is_ascii_alphabetic),
+            Some(b) => b.is_ascii_alphabetic(),
+            None => false,
+        })
+    }
+
+    /// Skip to just after the given pattern, or to the end of input
+    fn skip_past(&mut self, from: usize, pattern: &[u8]) {
+        self.pos = match memchr::memmem::find(&self.bytes()[from..], pattern) {
+            Some(end) => from + end + pattern.len(),
+            None => self.input.len(),
+        };
+    }
+}
+
+fn is_name_char(b: u8) -> bool {
+    !matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b'\x0C' | b'/' | b'>')
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(name) = self.raw_text.take() {
+            let end = self.find_end_tag(name).unwrap_or(self.input.len());
+            let text = &self.input[self.pos..end];
+
+            self.pos = end;
+
+            if !text.is_empty() {
+                return Some(Token::Text(text));
+            }
+        }
+
+        let bytes = self.bytes();
+
+        loop {
+            let start = self.pos;
+
+            if start >= bytes.len() {
+                return None;
+            }
+
+            let Some(lt) = self.find_markup(start) else {
+                self.pos = bytes.len();
+
+                return Some(Token::Text(&self.input[start..]));
+            };
+
+            if lt > start {
+                self.pos = lt;
+
+                return Some(Token::Text(&self.input[start..lt]));
+            }
+
+            let rest = &bytes[lt..];
+
+            match rest.get(1) {
+                Some(b'!') if rest.starts_with(b"<!--") => self.skip_past(lt + 4, b"-->"),
+                // doctype, CDATA or processing instruction
+                Some(b'!' | b'?') => self.skip_past(lt + 2, b">"),
+                Some(b'/') if rest.get(2).is_some_and(u8::is_ascii_alphabetic) => {
+                    let name_end =
+                        rest[2..].iter().position(|&b| !is_name_char(b)).map_or(rest.len(), |e| e + 2);
+                    let name = &self.input[(lt + 2)..(lt + name_end)];
+
+                    self.skip_past(lt + name_end, b">");
+
+                    return Some(Token::EndTag(name));
+                }
+                Some(b) if b.is_ascii_alphabetic() => {
+                    let name_end =
+                        rest[1..].iter().position(|&b| !is_name_char(b)).map_or(rest.len(), |e| e + 1);
+                    let name = &self.input[(lt + 1)..(lt + name_end)];
+
+                    let (attrs_end, next) = match self.find_tag_end(lt + name_end) {
+                        Some(end) => (end, end + 1),
+                        None => (bytes.len(), bytes.len()),
+                    };
+
+                    let raw_attrs = &self.input[(lt + name_end)..attrs_end];
+                    let self_closing = raw_attrs.ends_with('/');
+
+                    self.pos = next;
+
+                    if !self_closing {
+                        self.raw_text =
+                            RAW_TEXT.iter().find(|raw| raw.eq_ignore_ascii_case(name)).map(|_| name);
+                    }
+
+                    return Some(Token::StartTag(Tag {
+                        name,
+                        raw_attrs,
+                        self_closing,
+                    }));
+                }
+                _ => unreachable!("find_markup only stops at markup"),
+            }
+        }
+    }
+}
+
+/// `“` and `”`, accepted as quotes around attribute values
+const CURLY_QUOTES: [char; 2] = ['\u{201C}', '\u{201D}'];
+
+#[derive(Debug, Clone)]
+pub struct Attributes<'a> {
+    input: &'a str,
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = (&'a str, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_ws = |c: char| c.is_ascii_whitespace() || c == '/';
+
+        let input = self.input.trim_start_matches(is_ws);
+
+        if input.is_empty() {
+            self.input = input;
+            return None;
+        }
+
+        // first character is always part of the name, even if it's `=`
+        let first = input.chars().next().map_or(0, char::len_utf8);
+        let name_end = input[first..].find(|c: char| is_ws(c) || c == '=').map_or(input.len(), |e| e + first);
+        let name = &input[..name_end];
+
+        let rest = input[name_end..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        let Some(rest) = rest.strip_prefix('=') else {
+            self.input = rest;
+            return Some((name, Cow::Borrowed("")));
+        };
+
+        let rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        let (value, rest) = match rest.as_bytes().first() {
+            Some(&q @ (b'"' | b'\'')) => match memchr::memchr(q, &rest.as_bytes()[1..]) {
+                Some(end) => (&rest[1..(end + 1)], &rest[(end + 2)..]),
+                None => (&rest[1..], ""),
+            },
+            // curly quotes from pasting through word processors, which may not even be paired correctly
+            _ if rest.starts_with(CURLY_QUOTES) => {
+                let inner = &rest[3..]; // both are 3 bytes in UTF-8
+
+                match inner.find(CURLY_QUOTES) {
+                    Some(end) => (&inner[..end], &inner[(end + 3)..]),
+                    None => (inner, ""),
+                }
+            }
+            _ => rest.split_at(rest.find(|c: char| c.is_ascii_whitespace()).unwrap_or(rest.len())),
+        };
+
+        self.input = rest;
+
+        Some((name, html_escape::decode_html_entities(value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizer() {
+        let fixture = r#"<!DOCTYPE html><html><HEAD>
+            <!-- <meta name="commented" content="out"> -->
+            <meta name=description content='a > b &amp; c' data-x="1"/>
+            <script>if (a<b) { document.write("<meta name=fake>") }</script>
+            <title>Title &amp; More</title>
+            1 < 2
+        </head>"#;
+
+        let tokens: Vec<_> =
+            Tokenizer::new(fixture).filter(|t| !matches!(t, Token::Text(t) if t.trim().is_empty())).collect();
+
+        let Token::StartTag(ref meta) = tokens[2] else {
+            panic!("expected meta tag, got {:?}", tokens[2]);
+        };
+
+        assert_eq!(meta.name, "meta");
+        assert!(meta.self_closing);
+        assert_eq!(
+            meta.attrs().collect::<Vec<_>>(),
+            [
+                ("name", "description".into()),
+                ("content", "a > b & c".into()),
+                ("data-x", "1".into())
+            ]
+        );
+
+        assert!(matches!(tokens[1], Token::StartTag(ref tag) if tag.name == "HEAD"));
+        assert_eq!(
+            tokens[4],
+            Token::Text(r#"if (a<b) { document.write("<meta name=fake>") }"#)
+        );
+        assert_eq!(tokens[5], Token::EndTag("script"));
+        assert_eq!(tokens[7], Token::Text("Title &amp; More"));
+        assert!(tokens.iter().any(|t| matches!(t, Token::Text(t) if t.trim() == "1 < 2")));
+        assert_eq!(tokens.last(), Some(&Token::EndTag("head")));
+    }
+
+    #[test]
+    fn test_non_ascii_attributes() {
+        let fixture =
+            "<img 名前=値 alt=”日本語” data-x=“a b”><meta property=”og:title” content=”Curly Title”>";
+
+        let tokens: Vec<_> = Tokenizer::new(fixture).collect();
+
+        let (Token::StartTag(ref img), Token::StartTag(ref meta)) = (&tokens[0], &tokens[1]) else {
+            panic!("expected two tags, got {tokens:?}");
+        };
+
+        assert_eq!(
+            img.attrs().collect::<Vec<_>>(),
+            [
+                ("名前", "値".into()),
+                ("alt", "日本語".into()),
+                ("data-x", "a b".into())
+            ]
+        );
+
+        assert_eq!(
+            meta.attrs().collect::<Vec<_>>(),
+            [("property", "og:title".into()), ("content", "Curly Title".into())]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use embed::{EmbedField, EmbedV1};
+
 pub fn url_root(url: &str) -> (bool, &str, &str) {
     // https: / / whatever.com /
     let root_idx = url.split('/').map(|s| s.len()).take(3).sum::<usize>();
@@ -5,3 +7,74 @@ pub fn url_root(url: &str) -> (bool, &str, &str) {
     let https = root.starts_with("https://");
     (https, root, if https { &root[8..] } else { &root[7..] })
 }
+
+/// Format an ISO-8601 duration such as `PT1H2M3S` as `1:02:03`
+pub fn format_duration(duration: &str) -> Option<String> {
+    let mut rest = duration.strip_prefix('P')?;
+    let mut seconds = 0u64;
+    let mut time = false;
+
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('T') {
+            time = true;
+            rest = r;
+            continue;
+        }
+
+        let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let value: f64 = rest[..end].parse().ok()?;
+
+        seconds += match (rest.as_bytes()[end], time) {
+            (b'D', false) => value * 86400.0,
+            (b'H', true) => value * 3600.0,
+            (b'M', true) => value * 60.0,
+            (b'S', true) => value,
+            _ => return None,
+        } as u64;
+
+        rest = &rest[end + 1..];
+    }
+
+    Some(format_seconds(seconds))
+}
+
+/// Format a number of seconds as `1:02:03`
+pub fn format_seconds(seconds: u64) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    match h {
+        0 => format!("{m}:{s:02}"),
+        _ => format!("{h}:{m:02}:{s:02}"),
+    }
+}
+
+/// Add a `Duration` field from an ISO-8601 duration, unless the embed already has one
+pub fn add_duration_field(embed: &mut EmbedV1, duration: &str) {
+    if let Some(duration) = format_duration(duration) {
+        push_duration_field(embed, &duration);
+    }
+}
+
+/// Add a `Duration` field that's already formatted, unless the embed already has one
+pub fn push_duration_field(embed: &mut EmbedV1, duration: &str) {
+    if !embed.fields.iter().any(|f| f.name == "Duration") {
+        embed.fields.push(EmbedField {
+            name: From::from("Duration"),
+            value: From::from(duration),
+            ..EmbedField::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration("PT1M33S").as_deref(), Some("1:33"));
+        assert_eq!(format_duration("PT2H5S").as_deref(), Some("2:00:05"));
+        assert_eq!(format_duration("P0DT0H10M0S").as_deref(), Some("10:00"));
+        assert_eq!(format_duration("1:33"), None);
+    }
+}
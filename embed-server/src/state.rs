@@ -1,8 +1,4 @@
-use crate::{
-    cache::{storage::CacheFactory, EmbedCache},
-    config::Config,
-    extractors::Extractor,
-};
+use crate::{cache::EmbedCache, config::Config, extractors::Extractor};
 
 use hmac::{digest::Key, Mac};
 pub type Hmac = hmac::SimpleHmac<sha1::Sha1>;
@@ -16,6 +12,8 @@ pub struct ServiceState {
     pub client: reqwest::Client,
     pub extractors: Vec<Box<dyn Extractor>>,
     pub cache: EmbedCache,
+    pub html_stats: crate::extractors::generic::stats::HtmlCounters,
+    pub favicons: crate::extractors::generic::favicon::FaviconCache,
 }
 
 use embed::v1::UrlSignature;
@@ -25,9 +23,13 @@ impl ServiceState {
         ServiceState {
             #[allow(unused_imports, unreachable_patterns)]
             cache: {
-                use crate::cache::storage::CacheNameInner;
+                use crate::cache::{storage::CacheNameInner, tier::Tier};
+                use embed::timestamp::Duration;
 
-                let mut cache = EmbedCache::new(config.parsed.cache_size);
+                let mut cache = EmbedCache::new(
+                    config.parsed.memory_budget,
+                    config.parsed.memory_max_ttl.map(|secs| Duration::seconds(secs as i64)),
+                );
 
                 let raw_configs = &config.parsed.cache;
                 let mut sorted_configs = raw_configs.iter().collect::<Vec<_>>();
@@ -35,21 +37,28 @@ impl ServiceState {
                 sorted_configs.sort_by_key(|c| c.0.order);
 
                 for (name, config) in sorted_configs {
-                    let storage = match name.inner {
-                        #[cfg(feature = "cache_redis")]
-                        CacheNameInner::Redis => crate::cache::storage::redis::RedisCache::create(config),
-
-                        #[cfg(feature = "cache_rusqlite")]
-                        CacheNameInner::Sqlite => crate::cache::storage::sqlite::SqliteCache::create(config),
-
-                        #[cfg(feature = "cache_redb")]
-                        CacheNameInner::Redb => crate::cache::storage::redb::RedbCache::create(config),
+                    #[cfg(feature = "cache_redis")]
+                    if name.inner == CacheNameInner::Redis {
+                        use crate::cache::invalidation::InvalidationBus;
+
+                        if let Some(bus) =
+                            InvalidationBus::create(config).expect("Unable to create cache invalidation bus")
+                        {
+                            cache.set_invalidation_bus(bus);
+                        }
+
+                        if let Some(lock) = crate::cache::single_flight::DistributedLock::create(config)
+                            .expect("Unable to create single-flight lock")
+                        {
+                            cache.set_distributed_lock(lock);
+                        }
+                    }
 
-                        // impossible when compiled with any of the above features
-                        _ => break,
-                    };
+                    let storage = name.inner.create(config).expect("Unable to create cache storage backend");
 
-                    cache.add_storage(storage.expect("Unable to create cache storage backend"))
+                    cache.add_storage(
+                        Tier::create(name.inner, storage, config).expect("Invalid cache tier config"),
+                    );
                 }
 
                 cache
@@ -104,6 +113,9 @@ impl ServiceState {
                 extractors
             },
 
+            html_stats: Default::default(),
+            favicons: Default::default(),
+
             config,
         }
     }
@@ -123,4 +135,19 @@ impl ServiceState {
 
         None
     }
+
+    /// Check a signature made with [`ServiceState::sign`], always false if there is no signing key
+    pub fn verify(&self, value: &[u8], signature: &str) -> bool {
+        use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let Some(key) = self.signing_key.as_ref() else {
+            return false;
+        };
+
+        let Ok(sig) = URL_SAFE_NO_PAD.decode(signature.trim()) else {
+            return false;
+        };
+
+        Hmac::new(key).chain_update(value).verify_slice(&sig).is_ok()
+    }
 }
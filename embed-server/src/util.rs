@@ -3,6 +3,8 @@ use std::{
     fmt::{self, Write},
 };
 
+use embed::timestamp::Timestamp;
+
 pub fn format_list<I, T>(mut out: impl Write, list: impl IntoIterator<IntoIter = I>) -> Result<(), fmt::Error>
 where
     I: Iterator<Item = T>,
@@ -24,6 +26,19 @@ where
     Ok(())
 }
 
+/// Write a timestamp as `<t:unix>` when markdown is enabled, so clients can show it in the user's own timezone,
+/// otherwise as ISO-8601
+pub fn write_timestamp(mut out: impl Write, ts: Timestamp, markdown: bool) -> fmt::Result {
+    match markdown {
+        true => write!(
+            out,
+            "<t:{}>",
+            ts.duration_since(Timestamp::UNIX_EPOCH).whole_seconds()
+        ),
+        false => write!(out, "{ts}"),
+    }
+}
+
 /// Removes redundant newlines from the text,
 /// collapsing multiple newlines into a maximum of two.
 ///